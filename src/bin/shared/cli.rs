@@ -1,4 +1,5 @@
-use clap::{Args, Parser};
+use clap::{Args, Parser, Subcommand};
+use lifec_registry::DEFAULT_PARALLELISM;
 use serde::Serialize;
 use std::path::PathBuf;
 
@@ -119,3 +120,76 @@ pub struct MirrorSettings {
     #[clap(skip)]
     pub artifact_type: Option<String>,
 }
+
+/// Settings for managing content cached by the mirror,
+///
+#[derive(Args, Clone)]
+pub struct CacheSettings {
+    /// Work directory of the mirror,
+    ///
+    /// If None, the world directory of the registry is used,
+    ///
+    #[clap(long)]
+    pub work_dir: Option<PathBuf>,
+    #[clap(subcommand)]
+    pub command: CacheCommands,
+}
+
+/// Enumeration of cache subcommands,
+///
+#[derive(Subcommand, Clone)]
+pub enum CacheCommands {
+    /// Lists cached content, most recently accessed first,
+    ///
+    Ls,
+    /// Evicts least recently accessed content until the cache is under quota,
+    ///
+    Gc {
+        /// Maximum size of the cache, Ex. 512M, 10G
+        ///
+        #[clap(long)]
+        quota: String,
+    },
+    /// Pins a reference so that it, and the content it refers to, is never evicted,
+    ///
+    /// Ex. example.azurecr.io/library/redis:7, example.azurecr.io/library/redis@sha256:...
+    ///
+    Pin { reference: String },
+    /// Removes a reference from the pin list,
+    ///
+    Unpin { reference: String },
+    /// Evicts all content that is not pinned,
+    ///
+    Purge,
+}
+
+/// Settings for prefetching images into the mirror's cache,
+///
+#[derive(Args, Clone)]
+pub struct PrefetchSettings {
+    /// Image references to prefetch, Ex. example.azurecr.io/library/redis:7
+    ///
+    /// References w/o a registry are resolved against the current registry, or Docker Hub if there is none,
+    ///
+    pub references: Vec<String>,
+    /// File w/ an image reference per line, lines starting w/ `#` are skipped,
+    ///
+    #[clap(long)]
+    pub from_file: Option<PathBuf>,
+    /// Prefetch images for all platforms in an index, instead of only the host platform,
+    ///
+    #[clap(long, action)]
+    pub all_platforms: bool,
+    /// Platform to prefetch images for, Ex. linux/arm64, linux/arm/v7, defaults to the host platform,
+    ///
+    #[clap(long)]
+    pub platform: Option<String>,
+    /// Maximum number of blobs to download at the same time,
+    ///
+    #[clap(long, default_value_t = DEFAULT_PARALLELISM)]
+    pub parallelism: usize,
+    /// Address of the mirror to pull through, this can also be a plain registry,
+    ///
+    #[clap(long, default_value_t = String::from("localhost:8578"))]
+    pub mirror_address: String,
+}
@@ -1,6 +1,13 @@
 use clap::Subcommand;
 use lifec::host::HostSettings;
 use lifec::prelude::*;
+use lifec_registry::cache::parse_size;
+use lifec_registry::cache::CacheKind;
+use lifec_registry::cache::Eviction;
+use lifec_registry::BlobStore;
+use lifec_registry::ManifestCache;
+use lifec_registry::Platform;
+use lifec_registry::Prefetch;
 use lifec_registry::hosts_config::DefaultHost;
 use lifec_registry::hosts_config::MirrorHost;
 use lifec_registry::RegistryProxy;
@@ -12,6 +19,9 @@ use tracing::Level;
 
 use super::default_mirror_engine;
 use super::default_mirror_root;
+use super::CacheCommands;
+use super::CacheSettings;
+use super::PrefetchSettings;
 use super::MirrorSettings;
 use super::ACR;
 
@@ -31,6 +41,12 @@ pub enum Commands {
     /// Prints diagnostic information about mirror components,
     ///
     Dump,
+    /// Manages content cached by the mirror,
+    ///
+    Cache(CacheSettings),
+    /// Warms the mirror's cache by pulling a list of images through it,
+    ///
+    Prefetch(PrefetchSettings),
 }
 
 impl Commands {
@@ -185,6 +201,110 @@ impl Commands {
                 host.print_engine_event_graph();
                 host.print_lifecycle_graph();
             }
+            Commands::Prefetch(PrefetchSettings {
+                mut references,
+                from_file,
+                all_platforms,
+                platform,
+                parallelism,
+                mirror_address,
+            }) => {
+                if let Some(from_file) = from_file {
+                    let file = tokio::fs::read_to_string(&from_file)
+                        .await
+                        .expect("should be able to read image list");
+
+                    references.extend(
+                        file.lines()
+                            .map(str::trim)
+                            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                            .map(str::to_string),
+                    );
+                }
+
+                let mut prefetch = Prefetch::new(mirror_address)
+                    .with_all_platforms(all_platforms)
+                    .with_parallelism(parallelism);
+                if let Some(platform) = platform {
+                    match Platform::parse(&platform) {
+                        Some(platform) => prefetch = prefetch.with_platform(platform),
+                        None => warn!("Could not parse platform {platform}, using the host platform"),
+                    }
+                }
+                if let Some(registry) = registry.as_ref() {
+                    prefetch = prefetch.with_default_namespace(format!("{registry}.{registry_host}"));
+                }
+
+                let summary = prefetch.run(references).await;
+                println!(
+                    "Prefetched {} images, {} manifests, {} blobs, {} bytes",
+                    summary.images, summary.manifests, summary.blobs, summary.bytes
+                );
+
+                if !summary.failed.is_empty() {
+                    println!("Failed:");
+                    for failed in summary.failed.iter() {
+                        println!("  {failed}");
+                    }
+                    std::process::exit(1);
+                }
+            }
+            Commands::Cache(CacheSettings { work_dir, command }) => {
+                let work_dir = work_dir.unwrap_or(world_dir);
+                let eviction = Eviction::new(
+                    BlobStore::new(work_dir.join("blobs")),
+                    ManifestCache::new(work_dir.join("manifests")),
+                    work_dir.join("pins.json"),
+                );
+
+                match command {
+                    CacheCommands::Ls => {
+                        let entries = eviction.entries().await.expect("should be able to list cache");
+                        for entry in entries.iter() {
+                            println!(
+                                "{:<8} {:<12} {:<6} {}",
+                                match entry.kind {
+                                    CacheKind::Blob => "blob",
+                                    CacheKind::Manifest => "manifest",
+                                },
+                                entry.local.size,
+                                if entry.pinned { "pinned" } else { "" },
+                                entry.local.digest
+                            );
+                        }
+
+                        let total = entries.iter().map(|e| e.local.size).sum::<u64>();
+                        println!("{} entries, {total} bytes", entries.len());
+                    }
+                    CacheCommands::Gc { quota } => {
+                        let quota = parse_size(&quota).expect("should be a valid size, Ex. 10G");
+                        let summary = eviction
+                            .with_quota(quota)
+                            .gc()
+                            .await
+                            .expect("should be able to evict content");
+                        println!(
+                            "Evicted {} entries, reclaimed {} bytes, {} bytes remaining",
+                            summary.evicted, summary.reclaimed, summary.remaining
+                        );
+                    }
+                    CacheCommands::Pin { reference } => {
+                        eviction.pin(&reference).await.expect("should be able to pin reference");
+                        event!(Level::INFO, "Pinned {reference}");
+                    }
+                    CacheCommands::Unpin { reference } => {
+                        eviction.unpin(&reference).await.expect("should be able to unpin reference");
+                        event!(Level::INFO, "Unpinned {reference}");
+                    }
+                    CacheCommands::Purge => {
+                        let summary = eviction.purge().await.expect("should be able to purge cache");
+                        println!(
+                            "Evicted {} entries, reclaimed {} bytes, {} bytes remaining",
+                            summary.evicted, summary.reclaimed, summary.remaining
+                        );
+                    }
+                }
+            }
             _ => {}
         }
     }
@@ -5,6 +5,9 @@ pub mod macros;
 mod cli;
 use cli::ACR;
 use cli::MirrorSettings;
+use cli::CacheSettings;
+use cli::CacheCommands;
+use cli::PrefetchSettings;
 
 mod commands;
 use commands::Commands;
@@ -17,11 +17,16 @@ pub use image_manifest::ImageManifest;
 mod image_index;
 pub use image_index::ImageIndex;
 
-mod manifests;
-pub use manifests::Manifests;
+mod raw_content;
+pub use raw_content::RawContent;
+
+mod media_types;
+pub use media_types::MediaTypeKind;
+pub use media_types::MediaTypeRegistry;
 
 mod registry;
 pub use registry::Registry;
+pub use registry::UpstreamApi;
 
 mod contents;
 pub use contents::Contents;
@@ -32,6 +37,53 @@ pub use upstream::Upstream;
 mod local;
 pub use local::Local;
 
+mod hasher;
+pub use hasher::Hasher;
+
+mod digest;
+pub use digest::Digest;
+pub use digest::Algorithm;
+pub use hasher::read_verified;
+
+mod blob_store;
+pub use blob_store::BlobStore;
+pub use blob_store::BlobWriter;
+pub use blob_store::Recovery;
+
+mod cache_index;
+pub use cache_index::CacheIndex;
+pub use cache_index::IndexEntry;
+
+mod access;
+pub use access::AccessCache;
+pub use access::DEFAULT_ACCESS_CHECK_TTL;
+
+mod manifest_cache;
+pub use manifest_cache::ManifestCache;
+pub use manifest_cache::CachedManifest;
+
+mod single_flight;
+pub use single_flight::SingleFlight;
+pub use single_flight::Flight;
+pub use single_flight::FlightGuard;
+pub use single_flight::Follower;
+
+mod peers;
+pub use peers::Peers;
+pub use peers::PEER_TOKEN_HEADER;
+
+mod range;
+pub use range::ByteRange;
+pub use range::RangeRequest;
+
+mod eviction;
+pub use eviction::Eviction;
+pub use eviction::CacheEntry;
+pub use eviction::CacheKind;
+pub use eviction::EvictionSummary;
+pub use eviction::parse_size;
+pub use eviction::DEFAULT_GC_INTERVAL;
+
 pub mod consts {
     pub use super::image_index::DOCKER_MANIFEST_LIST;
     pub use super::image_index::OCI_IMAGE_INDEX;
@@ -43,4 +95,6 @@ pub mod consts {
     pub use super::registry::consts::UPGRADE_IF_STREAMABLE_HEADER;
     pub use super::registry::consts::ACCEPT_IF_SUFFIX_HEADER;
     pub use super::registry::consts::ENABLE_MIRROR_IF_SUFFIX_HEADER;
+    pub use super::registry::consts::API_VERSION_HEADER;
+    pub use super::registry::consts::API_VERSION;
 }
\ No newline at end of file
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use hyper::StatusCode;
+
+use super::Hasher;
+
+/// Default amount of time the result of an access check is cached,
+///
+pub const DEFAULT_ACCESS_CHECK_TTL: Duration = Duration::from_secs(30);
+
+/// Caches the result of upstream access checks,
+///
+/// Content in the local store can be shared by every namespace the mirror serves. Before content that was fetched from one
+/// repository is served to a request for a different repository, the mirror checks w/ the upstream that the caller can
+/// read the content from the repository it asked for. The result is cached for a short time, keyed by the repository,
+/// the digest and the caller's credentials, so that a burst of pulls only checks once.
+///
+#[derive(Debug, Clone)]
+pub struct AccessCache {
+    /// Amount of time a result is cached,
+    ///
+    ttl: Duration,
+    /// Map of keys to the status the upstream returned, and when it was returned,
+    ///
+    results: Arc<Mutex<HashMap<String, (StatusCode, Instant)>>>,
+}
+
+impl Default for AccessCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_ACCESS_CHECK_TTL)
+    }
+}
+
+impl AccessCache {
+    /// Returns a new access cache,
+    ///
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            results: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Returns the key for an access check,
+    ///
+    /// The caller's authorization header is hashed, so that credentials are not kept in memory,
+    ///
+    pub fn key(source: impl AsRef<str>, digest: impl AsRef<str>, authorization: Option<&str>) -> String {
+        let caller = authorization
+            .map(|authorization| {
+                let mut hasher = Hasher::default();
+                hasher.update(authorization);
+                hasher.finish()
+            })
+            .unwrap_or_default();
+
+        format!("{}@{}#{caller}", source.as_ref(), digest.as_ref())
+    }
+
+    /// Returns the cached status of an access check, if it has not expired,
+    ///
+    pub fn get(&self, key: impl AsRef<str>) -> Option<StatusCode> {
+        let results = self.results.lock().expect("should be able to lock access cache");
+
+        results
+            .get(key.as_ref())
+            .filter(|(_, checked)| checked.elapsed() < self.ttl)
+            .map(|(status, _)| *status)
+    }
+
+    /// Caches the status of an access check,
+    ///
+    /// Only conclusive results are cached, errors such as an unavailable upstream are checked again on the next request.
+    ///
+    pub fn insert(&self, key: impl Into<String>, status: StatusCode) {
+        if !Self::is_conclusive(status) {
+            return;
+        }
+
+        let mut results = self.results.lock().expect("should be able to lock access cache");
+        results.retain(|_, (_, checked)| checked.elapsed() < self.ttl);
+        results.insert(key.into(), (status, Instant::now()));
+    }
+
+    /// Returns true if the status of an access check allows content to be served,
+    ///
+    pub fn is_allowed(status: StatusCode) -> bool {
+        status.is_success()
+    }
+
+    /// Returns true if the status of an access check can be cached,
+    ///
+    fn is_conclusive(status: StatusCode) -> bool {
+        status.is_success()
+            || status == StatusCode::UNAUTHORIZED
+            || status == StatusCode::FORBIDDEN
+            || status == StatusCode::NOT_FOUND
+    }
+}
+
+#[allow(unused_imports)]
+mod tests {
+    use std::time::Duration;
+
+    use hyper::StatusCode;
+
+    use super::AccessCache;
+
+    #[test]
+    fn test_access_cache() {
+        let access = AccessCache::default();
+        let key = AccessCache::key("test.azurecr.io/private", "sha256:abc", Some("Bearer token"));
+        assert_ne!(key, AccessCache::key("test.azurecr.io/private", "sha256:abc", Some("Bearer other")));
+        assert!(access.get(&key).is_none());
+
+        access.insert(&key, StatusCode::FORBIDDEN);
+        assert_eq!(Some(StatusCode::FORBIDDEN), access.get(&key));
+
+        // Errors are not cached
+        let unavailable = AccessCache::key("test.azurecr.io/private", "sha256:abc", None);
+        access.insert(&unavailable, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(access.get(&unavailable).is_none());
+
+        // Results expire
+        let access = AccessCache::new(Duration::ZERO);
+        access.insert(&key, StatusCode::OK);
+        assert!(access.get(&key).is_none());
+    }
+}
@@ -2,12 +2,11 @@ use std::collections::BTreeMap;
 
 use crate::ProxyTarget;
 use hyper::Method;
-use lifec::prelude::{BlobSource, ContentBroker, MemoryBlobSource, Sha256Digester, ThunkContext, Component, DefaultVecStorage};
+use lifec::prelude::{ThunkContext, Component, DefaultVecStorage};
 use tracing::{event, Level};
-use std::io::Write;
 use serde::{Deserialize, Serialize};
 
-use super::Descriptor;
+use super::{Descriptor, RawContent};
 
 /// Manifest struct for stored artifacts related to an image
 ///
@@ -47,28 +46,15 @@ impl ArtifactManifest {
     pub async fn upload(&self, thunk_context: &ThunkContext) {
         if let Some(proxy_target) = ProxyTarget::try_from(thunk_context).ok() {
             let request = proxy_target.start_request();
-            let bytes = serde_json::to_vec(&self).expect("should be serializable");
 
-            // TODO -- this will eventually be generalized
-            let mut blob_source = MemoryBlobSource::default();
-            blob_source
-                .new("")
-                .as_mut()
-                .write_all(&bytes)
-                .expect("can write");
-            let digester = Sha256Digester().format(blob_source);
-            let blob_device = digester
-                .hash_map()
-                .drain()
-                .take(1)
-                .next()
-                .expect("should exist");
+            // The mirror is creating this content, so the manifest is serialized once and uploaded w/ the digest of those bytes
+            let raw = RawContent::create(self).expect("should be serializable");
 
             let request = request
                 .content_type(&self.media_type)
-                .uri_str(proxy_target.manifest_with(blob_device.0))
+                .uri_str(proxy_target.manifest_with(raw.digest()))
                 .method(Method::PUT)
-                .body(bytes);
+                .body(raw.into_bytes());
 
             let response = proxy_target
                 .send_request(request)
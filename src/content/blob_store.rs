@@ -0,0 +1,588 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use tokio::io::AsyncWriteExt;
+use tracing::{debug, info, warn};
+
+use crate::Error;
+
+use super::cache_index::{now, IndexEntry, INDEX_FILE};
+use super::{CacheIndex, Digest, Hasher, Local};
+
+/// Counter used to keep temporary file names unique within this process,
+///
+static PARTIAL_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Partial files that are being written by this process, skipped by recovery since it can run while the mirror is serving,
+///
+static ACTIVE_PARTIALS: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+/// Extension used for content that is still being written,
+///
+pub const PARTIAL_EXTENSION: &'static str = "partial";
+
+/// Writer for streaming content into a blob store,
+///
+/// Content is hashed as it is written, and is only moved into the store by `commit` if it matches the expected digest.
+/// If the writer is dropped before it is committed, the partial file is removed.
+///
+pub struct BlobWriter {
+    /// Expected digest of the content,
+    ///
+    digest: String,
+    /// Path the content is moved to once verified,
+    ///
+    path: PathBuf,
+    /// Path the content is written to,
+    ///
+    partial: PathBuf,
+    /// Partial file,
+    ///
+    file: tokio::fs::File,
+    /// Hasher for the content written so far,
+    ///
+    hasher: Hasher,
+    /// Number of bytes written so far,
+    ///
+    size: u64,
+    /// Index the content is recorded in once committed,
+    ///
+    index: CacheIndex,
+    /// Media type of the content, if known,
+    ///
+    media_type: Option<String>,
+    /// Repository the content was fetched from, if known,
+    ///
+    source: Option<String>,
+    /// Marks the partial file as being written,
+    ///
+    _active: ActivePartial,
+}
+
+/// Marks a partial file as being written by this process until dropped,
+///
+struct ActivePartial(PathBuf);
+
+/// Summary of a recovery scan,
+///
+#[derive(Debug, Clone, Default)]
+pub struct Recovery {
+    /// Number of entries that were verified,
+    ///
+    pub verified: usize,
+    /// Number of partial downloads that were removed,
+    ///
+    pub partial: usize,
+    /// Number of entries removed because their content no longer matched their digest,
+    ///
+    pub corrupted: usize,
+    /// Number of index entries removed because their content no longer exists,
+    ///
+    pub missing: usize,
+    /// Number of entries that were added to the index,
+    ///
+    pub added: usize,
+}
+
+/// Digest-addressed blob store on the local filesystem,
+///
+/// Blobs are stored under `<root>/<algorithm>/<hex>`. Content is written to a partial file first and is only
+/// moved into place after it has been verified against its digest, so a reader never observes an incomplete blob.
+/// Metadata for each blob is kept in a durable index at `<root>/index.json`.
+///
+#[derive(Debug, Clone)]
+pub struct BlobStore {
+    /// Root directory of the store,
+    ///
+    root: PathBuf,
+    /// Index of the content in this store,
+    ///
+    index: CacheIndex,
+}
+
+impl BlobStore {
+    /// Returns a blob store rooted at the given directory,
+    ///
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        let root = root.into();
+        Self {
+            index: CacheIndex::open(root.join(INDEX_FILE)),
+            root,
+        }
+    }
+
+    /// Returns the index of this store,
+    ///
+    pub fn index(&self) -> &CacheIndex {
+        &self.index
+    }
+
+    /// Returns the root directory of this store,
+    ///
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns the path a digest is stored at, returns None if the digest is not a valid sha256/sha512 digest,
+    ///
+    pub fn path(&self, digest: impl AsRef<str>) -> Option<PathBuf> {
+        Digest::parse(digest)
+            .ok()
+            .map(|d| self.root.join(d.algorithm().name()).join(d.hex()))
+    }
+
+    /// Returns the local content for a digest if it exists in the store, and updates its last access time,
+    ///
+    pub async fn get(&self, digest: impl AsRef<str>) -> Option<Local> {
+        let path = self.path(digest.as_ref())?;
+
+        match tokio::fs::metadata(&path).await {
+            Ok(metadata) if metadata.is_file() => {
+                if let Err(err) = self.index.touch(digest.as_ref(), None).await {
+                    warn!("Could not update cache index, {err}");
+                }
+
+                Some(Local {
+                    path,
+                    digest: digest.as_ref().to_string(),
+                    size: metadata.len(),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns all content in the store w/ the time it was last accessed,
+    ///
+    /// Partial content that is still being written is skipped. The access time is read from the index, content that is
+    /// not in the index uses the access time of the file.
+    ///
+    pub async fn entries(&self) -> Result<Vec<(Local, SystemTime)>, Error> {
+        let index = self.index.entries();
+        let mut entries = vec![];
+
+        for algorithm in ["sha256", "sha512"] {
+            let dir = self.root.join(algorithm);
+            if !dir.is_dir() {
+                continue;
+            }
+
+            let mut read_dir = tokio::fs::read_dir(&dir).await?;
+            while let Some(entry) = read_dir.next_entry().await? {
+                let path = entry.path();
+                let digest = match path.file_name().and_then(|n| n.to_str()) {
+                    Some(hex) => format!("{algorithm}:{hex}"),
+                    None => continue,
+                };
+
+                // Skips partial content and metadata files
+                if self.path(&digest).is_none() {
+                    continue;
+                }
+
+                let metadata = entry.metadata().await?;
+                if metadata.is_file() {
+                    let accessed = match index.get(&digest) {
+                        Some(entry) => SystemTime::UNIX_EPOCH + Duration::from_secs(entry.last_access),
+                        None => metadata.accessed().or(metadata.modified())?,
+                    };
+                    entries.push((
+                        Local {
+                            path,
+                            digest,
+                            size: metadata.len(),
+                        },
+                        accessed,
+                    ));
+                }
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Verifies and writes content to the store, returns the local content that was written,
+    ///
+    pub async fn put(&self, digest: impl AsRef<str>, content: impl AsRef<[u8]>) -> Result<Local, Error> {
+        self.put_with(digest, content, None, None).await
+    }
+
+    /// Verifies and writes content w/ a known media type to the store, returns the local content that was written,
+    ///
+    /// If a source is passed, it is recorded as a repository the content was fetched from, see `CacheIndex::source`.
+    ///
+    pub async fn put_with(
+        &self,
+        digest: impl AsRef<str>,
+        content: impl AsRef<[u8]>,
+        media_type: Option<&str>,
+        source: Option<&str>,
+    ) -> Result<Local, Error> {
+        let digest = digest.as_ref();
+        let path = self
+            .path(digest)
+            .ok_or_else(|| Error::invalid_operation("unsupported digest format"))?;
+
+        let mut hasher = Hasher::for_digest(digest).unwrap_or_default();
+        hasher.update(content.as_ref());
+        let actual = hasher.finish();
+        if actual != digest {
+            warn!("Content did not match digest {digest}, skipping local store");
+            return Err(Error::digest_mismatch(digest, actual));
+        }
+
+        let partial = self.partial_path(&path);
+        let _active = ActivePartial::new(&partial);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        tokio::fs::write(&partial, content.as_ref()).await?;
+        if let Err(err) = tokio::fs::rename(&partial, &path).await {
+            tokio::fs::remove_file(&partial).await.ok();
+            return Err(err.into());
+        }
+
+        let size = content.as_ref().len() as u64;
+        if let Err(err) = self.index.insert(digest, size, media_type, source).await {
+            warn!("Could not update cache index, {err}");
+        }
+
+        debug!("Stored blob {digest}, {:?}", path);
+        Ok(Local {
+            path,
+            digest: digest.to_string(),
+            size,
+        })
+    }
+
+    /// Returns a writer for streaming content w/ the given digest into the store,
+    ///
+    pub async fn writer(&self, digest: impl AsRef<str>) -> Result<BlobWriter, Error> {
+        let digest = digest.as_ref();
+        let path = self
+            .path(digest)
+            .ok_or_else(|| Error::invalid_operation("unsupported digest format"))?;
+        let hasher =
+            Hasher::for_digest(digest).ok_or_else(|| Error::invalid_operation("unsupported digest format"))?;
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let partial = self.partial_path(&path);
+        let _active = ActivePartial::new(&partial);
+        let file = tokio::fs::File::create(&partial).await?;
+
+        Ok(BlobWriter {
+            digest: digest.to_string(),
+            path,
+            partial,
+            file,
+            hasher,
+            size: 0,
+            index: self.index.clone(),
+            media_type: None,
+            source: None,
+            _active,
+        })
+    }
+
+    /// Removes content and its metadata from the store,
+    ///
+    /// Readers that already opened the content can continue reading it, since the file is only unlinked,
+    ///
+    pub async fn remove(&self, digest: impl AsRef<str>) -> Result<(), Error> {
+        self.index.remove(digest.as_ref()).await?;
+
+        if let Some(path) = self.path(digest) {
+            let metadata = path.with_extension("json");
+            if metadata.exists() {
+                tokio::fs::remove_file(metadata).await?;
+            }
+
+            if path.exists() {
+                tokio::fs::remove_file(path).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Scans the store and repairs the index,
+    ///
+    /// Partial downloads left behind by a previous process are removed, and content is re-hashed so that entries that no
+    /// longer match their digest are removed. Index entries for content that no longer exists are dropped, and content
+    /// that is missing from the index is added.
+    ///
+    /// Recovery blocks while content is re-hashed, so it should be run on a blocking thread. Content being written while
+    /// recovery runs is left alone, so the store can be in use at the same time.
+    ///
+    pub fn recover(&self) -> Result<Recovery, Error> {
+        let mut recovery = Recovery::default();
+        let mut index = self.index.entries();
+        let mut removed = vec![];
+        let mut updated = BTreeMap::new();
+
+        for algorithm in ["sha256", "sha512"] {
+            let dir = self.root.join(algorithm);
+            if !dir.is_dir() {
+                continue;
+            }
+
+            for entry in std::fs::read_dir(&dir)? {
+                let path = entry?.path();
+                if !path.is_file() {
+                    continue;
+                }
+
+                let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+                if name.ends_with(PARTIAL_EXTENSION) {
+                    if ActivePartial::is_active(&path) {
+                        continue;
+                    }
+
+                    warn!("Removing partial download {:?}", path);
+                    std::fs::remove_file(&path)?;
+                    recovery.partial += 1;
+                    continue;
+                }
+
+                let digest = format!("{algorithm}:{name}");
+                if self.path(&digest).is_none() {
+                    continue;
+                }
+
+                if !verify_file(&path, &digest)? {
+                    warn!("Removing {digest}, content no longer matches digest");
+                    std::fs::remove_file(&path)?;
+                    std::fs::remove_file(path.with_extension("json")).ok();
+                    index.remove(&digest);
+                    removed.push(digest);
+                    recovery.corrupted += 1;
+                    continue;
+                }
+
+                let size = std::fs::metadata(&path)?.len();
+                match index.remove(&digest) {
+                    Some(entry) if entry.size == size => {}
+                    Some(entry) => {
+                        updated.insert(digest, IndexEntry { size, ..entry });
+                    }
+                    None => {
+                        debug!("Adding {digest} to cache index");
+                        recovery.added += 1;
+                        updated.insert(
+                            digest,
+                            IndexEntry {
+                                size,
+                                last_access: now(),
+                                ..Default::default()
+                            },
+                        );
+                    }
+                }
+
+                recovery.verified += 1;
+            }
+        }
+
+        // Anything left in the index no longer has content
+        for digest in index.into_keys() {
+            warn!("Removing {digest} from cache index, content no longer exists");
+            removed.push(digest);
+            recovery.missing += 1;
+        }
+
+        self.index.update_blocking(removed, updated)?;
+
+        if recovery.partial + recovery.corrupted + recovery.missing + recovery.added > 0 {
+            info!(
+                "Repaired {:?}, removed {} partial downloads, {} corrupted entries, {} missing entries, added {} entries",
+                self.root, recovery.partial, recovery.corrupted, recovery.missing, recovery.added
+            );
+        }
+        debug!("Verified {} entries in {:?}", recovery.verified, self.root);
+
+        Ok(recovery)
+    }
+
+    /// Returns a unique partial path next to the final path,
+    ///
+    pub(crate) fn partial_path(&self, path: &Path) -> PathBuf {
+        let id = PARTIAL_COUNTER.fetch_add(1, Ordering::Relaxed);
+        path.with_extension(format!("{}.{id}.{PARTIAL_EXTENSION}", std::process::id()))
+    }
+}
+
+impl BlobWriter {
+    /// Sets the media type recorded for this content,
+    ///
+    pub fn with_media_type(mut self, media_type: Option<impl Into<String>>) -> Self {
+        self.media_type = media_type.map(|m| m.into());
+        self
+    }
+
+    /// Sets the source recorded for this content, see `CacheIndex::source`,
+    ///
+    pub fn with_source(mut self, source: impl Into<String>) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+
+    /// Writes the next chunk of content,
+    ///
+    pub async fn write(&mut self, chunk: impl AsRef<[u8]>) -> Result<(), Error> {
+        self.hasher.update(chunk.as_ref());
+        self.file.write_all(chunk.as_ref()).await?;
+        self.size += chunk.as_ref().len() as u64;
+        Ok(())
+    }
+
+    /// Verifies the content that was written and moves it into the store, returns the local content,
+    ///
+    pub async fn commit(mut self) -> Result<Local, Error> {
+        self.file.flush().await?;
+
+        let actual = std::mem::take(&mut self.hasher).finish();
+        if actual != self.digest {
+            warn!("Content did not match digest {}, skipping local store", self.digest);
+            return Err(Error::digest_mismatch(&self.digest, actual));
+        }
+
+        tokio::fs::rename(&self.partial, &self.path).await?;
+
+        if let Err(err) = self
+            .index
+            .insert(&self.digest, self.size, self.media_type.as_deref(), self.source.as_deref())
+            .await
+        {
+            warn!("Could not update cache index, {err}");
+        }
+
+        debug!("Stored blob {}, {:?}", self.digest, self.path);
+        Ok(Local {
+            path: self.path.clone(),
+            digest: self.digest.clone(),
+            size: self.size,
+        })
+    }
+}
+
+impl Drop for BlobWriter {
+    fn drop(&mut self) {
+        // If the content was committed, the partial file was already moved into place
+        if self.partial.exists() {
+            std::fs::remove_file(&self.partial).ok();
+        }
+    }
+}
+
+impl ActivePartial {
+    /// Marks a partial file as being written,
+    ///
+    fn new(path: &Path) -> Self {
+        if let Ok(mut active) = ACTIVE_PARTIALS.lock() {
+            active.insert(path.to_path_buf());
+        }
+        Self(path.to_path_buf())
+    }
+
+    /// Returns true if the partial file is being written by this process,
+    ///
+    fn is_active(path: &Path) -> bool {
+        ACTIVE_PARTIALS
+            .lock()
+            .map(|active| active.contains(path))
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for ActivePartial {
+    fn drop(&mut self) {
+        if let Ok(mut active) = ACTIVE_PARTIALS.lock() {
+            active.remove(&self.0);
+        }
+    }
+}
+
+/// Returns true if the content of a file matches the digest,
+///
+fn verify_file(path: &Path, digest: &str) -> Result<bool, Error> {
+    let mut hasher = match Hasher::for_digest(digest) {
+        Some(hasher) => hasher,
+        None => return Ok(false),
+    };
+
+    let mut file = std::fs::File::open(path)?;
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(hasher.finish() == digest)
+}
+
+#[allow(unused_imports)]
+mod tests {
+    use super::BlobStore;
+
+    #[tokio::test]
+    async fn test_blob_store() {
+        let store = BlobStore::new(".test_blob_store");
+        let digest = "sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+        assert!(store.get(digest).await.is_none());
+        assert!(store.path("sha256:../../etc/passwd").is_none());
+        assert!(store.put(digest, b"not hello").await.is_err());
+
+        let local = store.put(digest, b"hello").await.expect("should store content");
+        assert_eq!(5, local.size);
+
+        let local = store.get(digest).await.expect("should exist");
+        assert_eq!(b"hello".to_vec(), tokio::fs::read(local.path).await.unwrap());
+
+        let entries = store.entries().await.expect("should list entries");
+        assert_eq!(1, entries.len());
+        assert_eq!(digest, entries[0].0.digest);
+
+        store.remove(digest).await.unwrap();
+        assert!(store.get(digest).await.is_none());
+
+        let mut writer = store.writer(digest).await.expect("should create writer");
+        writer.write(b"not ").await.unwrap();
+        writer.write(b"hello").await.unwrap();
+        assert!(writer.commit().await.is_err());
+        assert!(store.get(digest).await.is_none());
+
+        let mut writer = store.writer(digest).await.expect("should create writer");
+        writer.write(b"hel").await.unwrap();
+        writer.write(b"lo").await.unwrap();
+        let local = writer.commit().await.expect("should store content");
+        assert_eq!(5, local.size);
+        assert_eq!(1, store.entries().await.unwrap().len());
+        assert_eq!(Some(5), store.index().get(digest).map(|e| e.size));
+
+        // Recovery drops partial downloads and content that no longer matches its digest
+        std::fs::write(store.partial_path(&local.path), b"hel").unwrap();
+        let recovery = store.recover().expect("should recover");
+        assert_eq!(1, recovery.partial);
+        assert_eq!(1, recovery.verified);
+
+        std::fs::write(&local.path, b"world").unwrap();
+        let recovery = store.recover().expect("should recover");
+        assert_eq!(1, recovery.corrupted);
+        assert!(store.get(digest).await.is_none());
+        assert!(BlobStore::new(".test_blob_store").index().get(digest).is_none());
+
+        std::fs::remove_dir_all(".test_blob_store").unwrap();
+    }
+}
@@ -0,0 +1,474 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::Error;
+
+/// Name of the index file in the root of a store,
+///
+pub const INDEX_FILE: &'static str = "index.json";
+
+/// Minimum amount of time between writes of the index that only update access times,
+///
+pub const ACCESS_FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Amount of time after which a lock on the index file is considered to be left behind by a process that exited,
+///
+pub const INDEX_LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Amount of time to wait between attempts to lock the index file,
+///
+const INDEX_LOCK_RETRY: Duration = Duration::from_millis(10);
+
+/// Durable index of the content in a store,
+///
+/// The index is kept in memory and written atomically to disk, so that metadata survives restarts of the mirror. Writes
+/// that add or remove content are flushed right away, writes that only update access times are batched.
+///
+/// Other processes can share the index file, Ex. the `cache` commands while a mirror is running. Writes hold a lock file
+/// next to the index, and the index is reloaded before it is written so that only the changes made by this process are
+/// applied on top of the changes made by others.
+///
+#[derive(Debug, Clone)]
+pub struct CacheIndex {
+    /// Path to the index file,
+    ///
+    path: PathBuf,
+    /// In-memory state of the index,
+    ///
+    state: Arc<Mutex<IndexState>>,
+    /// Serializes writes of the index file, so that a newer snapshot is never overwritten by an older one,
+    ///
+    write_lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+/// Entry in the cache index,
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    /// Size of the content in bytes,
+    ///
+    pub size: u64,
+    /// Media type of the content, if known,
+    ///
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+    /// Seconds since the unix epoch when the content was last accessed,
+    ///
+    pub last_access: u64,
+    /// Repositories this content was fetched from, formatted as `<namespace>/<repo>`,
+    ///
+    #[serde(default)]
+    pub sources: BTreeSet<String>,
+}
+
+/// In-memory state of the index,
+///
+#[derive(Debug)]
+struct IndexState {
+    /// Map of digests to entries,
+    ///
+    entries: BTreeMap<String, IndexEntry>,
+    /// True if the in-memory state has changes that have not been written,
+    ///
+    dirty: bool,
+    /// Last time the index was written,
+    ///
+    flushed_at: Instant,
+    /// Changes that have not been written, applied to the index on disk when it is reloaded,
+    ///
+    pending: BTreeMap<String, Change>,
+}
+
+/// Change to an entry made by this process,
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Change {
+    /// Content was stored, the entry replaces the entry on disk,
+    ///
+    Insert,
+    /// Content was accessed, only updates the entry on disk if it still exists,
+    ///
+    Touch,
+    /// Content was removed,
+    ///
+    Remove,
+}
+
+/// Lock on an index file shared between processes, removed when dropped,
+///
+struct IndexLock {
+    /// Path to the lock file,
+    ///
+    path: PathBuf,
+}
+
+impl CacheIndex {
+    /// Opens the index at the given path, if the index cannot be read an empty index is returned,
+    ///
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+
+        let entries = match std::fs::read(&path) {
+            Ok(index) => serde_json::from_slice(&index).unwrap_or_else(|err| {
+                warn!("Could not parse cache index {:?}, starting w/ an empty index, {err}", path);
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        };
+
+        Self {
+            path,
+            state: Arc::new(Mutex::new(IndexState {
+                entries,
+                dirty: false,
+                flushed_at: Instant::now(),
+                pending: BTreeMap::new(),
+            })),
+            write_lock: Arc::new(tokio::sync::Mutex::new(())),
+        }
+    }
+
+    /// Returns the source recorded for content fetched from a repository in an upstream namespace,
+    ///
+    pub fn source(namespace: impl AsRef<str>, repo: impl AsRef<str>) -> String {
+        format!("{}/{}", namespace.as_ref(), repo.as_ref())
+    }
+
+    /// Returns the path of the index file,
+    ///
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the entry for a digest,
+    ///
+    pub fn get(&self, digest: impl AsRef<str>) -> Option<IndexEntry> {
+        self.lock().entries.get(digest.as_ref()).cloned()
+    }
+
+    /// Returns a copy of all entries in the index,
+    ///
+    pub fn entries(&self) -> BTreeMap<String, IndexEntry> {
+        self.lock().entries.clone()
+    }
+
+    /// Adds or updates the entry for content that was stored, and writes the index,
+    ///
+    pub async fn insert(
+        &self,
+        digest: impl AsRef<str>,
+        size: u64,
+        media_type: Option<&str>,
+        source: Option<&str>,
+    ) -> Result<(), Error> {
+        {
+            let mut state = self.lock();
+            let entry = state.entries.entry(digest.as_ref().to_string()).or_default();
+            entry.size = size;
+            entry.last_access = now();
+            if let Some(media_type) = media_type {
+                entry.media_type = Some(media_type.to_string());
+            }
+            entry.sources.extend(source.map(str::to_string));
+            state.pending.insert(digest.as_ref().to_string(), Change::Insert);
+            state.dirty = true;
+        }
+
+        self.flush().await
+    }
+
+    /// Records that content was accessed, optionally from a source,
+    ///
+    pub async fn touch(&self, digest: impl AsRef<str>, source: Option<&str>) -> Result<(), Error> {
+        let flush = {
+            let mut state = self.lock();
+            let mut added_source = false;
+            match state.entries.get_mut(digest.as_ref()) {
+                Some(entry) => {
+                    entry.last_access = now();
+                    if let Some(source) = source {
+                        added_source = entry.sources.insert(source.to_string());
+                    }
+                }
+                None => return Ok(()),
+            }
+            state
+                .pending
+                .entry(digest.as_ref().to_string())
+                .or_insert(Change::Touch);
+            state.dirty = true;
+
+            added_source || state.flushed_at.elapsed() >= ACCESS_FLUSH_INTERVAL
+        };
+
+        if flush {
+            self.flush().await
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Removes the entry for a digest, and writes the index,
+    ///
+    pub async fn remove(&self, digest: impl AsRef<str>) -> Result<(), Error> {
+        let removed = {
+            let mut state = self.lock();
+            let removed = state.entries.remove(digest.as_ref()).is_some();
+            if removed {
+                state.pending.insert(digest.as_ref().to_string(), Change::Remove);
+                state.dirty = true;
+            }
+            removed
+        };
+
+        if removed {
+            self.flush().await
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Writes the index if it has changes,
+    ///
+    /// The index on disk is reloaded and the changes of this process are applied to it, the result is written to a
+    /// temporary file first and then renamed over the previous index,
+    ///
+    pub async fn flush(&self) -> Result<(), Error> {
+        let _write = self.write_lock.lock().await;
+
+        if !self.lock().dirty {
+            return Ok(());
+        }
+
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let _lock = IndexLock::acquire(&self.path).await?;
+        let snapshot = match tokio::fs::read(&self.path).await {
+            Ok(index) => self.merge(Some(index))?,
+            Err(err) if err.kind() == ErrorKind::NotFound => self.merge(None)?,
+            Err(err) => return Err(err.into()),
+        };
+
+        let partial = self.path.with_extension("json.partial");
+        tokio::fs::write(&partial, snapshot).await?;
+        tokio::fs::rename(&partial, &self.path).await?;
+
+        debug!("Wrote cache index {:?}", self.path);
+        Ok(())
+    }
+
+    /// Removes and updates entries, and writes the index synchronously, used by recovery,
+    ///
+    pub(crate) fn update_blocking(
+        &self,
+        removed: impl IntoIterator<Item = String>,
+        updated: BTreeMap<String, IndexEntry>,
+    ) -> Result<(), Error> {
+        {
+            let mut state = self.lock();
+            for digest in removed {
+                state.entries.remove(&digest);
+                state.pending.insert(digest, Change::Remove);
+            }
+            for (digest, entry) in updated {
+                state.pending.insert(digest.clone(), Change::Insert);
+                state.entries.insert(digest, entry);
+            }
+            state.dirty = true;
+        }
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let _lock = IndexLock::acquire_blocking(&self.path)?;
+        let snapshot = match std::fs::read(&self.path) {
+            Ok(index) => self.merge(Some(index))?,
+            Err(err) if err.kind() == ErrorKind::NotFound => self.merge(None)?,
+            Err(err) => return Err(err.into()),
+        };
+
+        let partial = self.path.with_extension("json.partial");
+        std::fs::write(&partial, snapshot)?;
+        std::fs::rename(&partial, &self.path)?;
+        Ok(())
+    }
+
+    /// Applies pending changes to the index read from disk, replaces the in-memory entries, and returns the snapshot to write,
+    ///
+    /// If there is no index on disk, or it cannot be parsed, the in-memory entries are written as is.
+    ///
+    fn merge(&self, index: Option<Vec<u8>>) -> Result<Vec<u8>, Error> {
+        let disk = index.and_then(|index| {
+            serde_json::from_slice::<BTreeMap<String, IndexEntry>>(&index)
+                .map_err(|err| warn!("Could not parse cache index {:?}, overwriting it, {err}", self.path))
+                .ok()
+        });
+
+        let mut state = self.lock();
+        let state = &mut *state;
+        if let Some(mut entries) = disk {
+            for (digest, change) in state.pending.iter() {
+                match (change, state.entries.get(digest)) {
+                    (Change::Remove, _) => {
+                        entries.remove(digest);
+                    }
+                    (Change::Insert, Some(local)) => {
+                        let mut local = local.clone();
+                        if let Some(entry) = entries.get(digest) {
+                            local.sources.extend(entry.sources.iter().cloned());
+                        }
+                        entries.insert(digest.to_string(), local);
+                    }
+                    (Change::Touch, Some(local)) => {
+                        if let Some(entry) = entries.get_mut(digest) {
+                            entry.last_access = entry.last_access.max(local.last_access);
+                            entry.sources.extend(local.sources.iter().cloned());
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            state.entries = entries;
+        }
+
+        state.pending.clear();
+        state.dirty = false;
+        state.flushed_at = Instant::now();
+        Ok(serde_json::to_vec(&state.entries)?)
+    }
+
+    /// Locks the in-memory state,
+    ///
+    fn lock(&self) -> std::sync::MutexGuard<'_, IndexState> {
+        self.state.lock().expect("should be able to lock cache index")
+    }
+}
+
+impl IndexLock {
+    /// Locks an index file, waits until the lock is released by other processes,
+    ///
+    async fn acquire(index: &Path) -> Result<Self, Error> {
+        loop {
+            if let Some(lock) = Self::try_acquire(index)? {
+                return Ok(lock);
+            }
+            tokio::time::sleep(INDEX_LOCK_RETRY).await;
+        }
+    }
+
+    /// Locks an index file, blocks the current thread until the lock is released by other processes,
+    ///
+    fn acquire_blocking(index: &Path) -> Result<Self, Error> {
+        loop {
+            if let Some(lock) = Self::try_acquire(index)? {
+                return Ok(lock);
+            }
+            std::thread::sleep(INDEX_LOCK_RETRY);
+        }
+    }
+
+    /// Tries to create the lock file, returns None if another process holds the lock,
+    ///
+    /// A lock older than `INDEX_LOCK_TIMEOUT` is removed, so that a process that exited while holding it does not block
+    /// others forever.
+    ///
+    fn try_acquire(index: &Path) -> Result<Option<Self>, Error> {
+        let path = index.with_extension("json.lock");
+
+        match std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(_) => Ok(Some(Self { path })),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                let stale = std::fs::metadata(&path)
+                    .and_then(|m| m.modified())
+                    .ok()
+                    .and_then(|m| m.elapsed().ok())
+                    .map_or(false, |elapsed| elapsed > INDEX_LOCK_TIMEOUT);
+                if stale {
+                    warn!("Removing stale cache index lock {:?}", path);
+                    std::fs::remove_file(&path).ok();
+                }
+                Ok(None)
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+impl Drop for IndexLock {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.path).ok();
+    }
+}
+
+/// Returns the current time in seconds since the unix epoch,
+///
+pub(crate) fn now() -> u64 {
+    SystemTime::UNIX_EPOCH
+        .elapsed()
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[allow(unused_imports)]
+mod tests {
+    use super::CacheIndex;
+
+    #[tokio::test]
+    async fn test_cache_index() {
+        let path = std::path::PathBuf::from(".test_cache_index").join("index.json");
+        let digest = "sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+        let index = CacheIndex::open(&path);
+        index
+            .insert(digest, 5, Some("application/octet-stream"), Some("test.azurecr.io/library/hello"))
+            .await
+            .unwrap();
+        index
+            .touch(digest, Some(&CacheIndex::source("other.azurecr.io", "library/hello")))
+            .await
+            .unwrap();
+
+        // The index should survive a restart
+        let reopened = CacheIndex::open(&path);
+        let entry = reopened.get(digest).expect("should have an entry");
+        assert_eq!(5, entry.size);
+        assert_eq!(Some("application/octet-stream".to_string()), entry.media_type);
+        assert_eq!(2, entry.sources.len());
+        assert!(entry.sources.contains("other.azurecr.io/library/hello"));
+
+        reopened.remove(digest).await.unwrap();
+        assert!(CacheIndex::open(&path).get(digest).is_none());
+
+        // Writes from another process sharing the index are not overwritten
+        let other_digest = "sha256:486ea46224d1bb4fb680f34f7c9ad96a8f24ec88be73ea8e5a6c65260e9cb8a7";
+        let other = CacheIndex::open(&path);
+        reopened.insert(digest, 5, None, None).await.unwrap();
+        other.insert(other_digest, 5, None, None).await.unwrap();
+        assert!(other.get(digest).is_some());
+        let shared = CacheIndex::open(&path);
+        assert!(shared.get(digest).is_some());
+        assert!(shared.get(other_digest).is_some());
+
+        // Content removed by another process is not added back when it is accessed
+        other.remove(digest).await.unwrap();
+        reopened.touch(digest, Some("test.azurecr.io/library/hello")).await.unwrap();
+        assert!(reopened.get(digest).is_none());
+        assert!(reopened.get(other_digest).is_some());
+        assert!(CacheIndex::open(&path).get(digest).is_none());
+
+        std::fs::remove_dir_all(".test_cache_index").unwrap();
+    }
+}
@@ -4,7 +4,7 @@ use lifec::prelude::{AttributeIndex, Component, DefaultVecStorage, ThunkContext}
 use serde::{Deserialize, Serialize};
 use tracing::{debug, trace};
 
-use super::Platform;
+use super::{Digest, Platform};
 
 /// Registry descriptor data layout
 ///
@@ -23,7 +23,7 @@ pub struct Descriptor {
     #[serde(rename = "artifactType")]
     pub artifact_type: Option<String>,
     #[serde(rename = "digest")]
-    pub digest: String,
+    pub digest: Digest,
     #[serde(rename = "size")]
     pub size: u64,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -52,7 +52,7 @@ impl Descriptor {
             (Some(media_type), Some(body), Some(digest), artifact_type) => Some(Descriptor {
                 media_type,
                 artifact_type,
-                digest,
+                digest: Digest::parse(digest).ok()?,
                 size: body.len() as u64,
                 annotations: None,
                 urls: None,
@@ -100,7 +100,7 @@ impl Descriptor {
                 desc.platform = Some(Platform {
                     architecture: platform.platform_arch.unwrap_or_default(),
                     os: platform.platform_os.unwrap_or_default(),
-                    variant: None,
+                    ..Default::default()
                 });
             }
 
@@ -124,7 +124,7 @@ impl Descriptor {
                     platform: Some(Platform {
                         architecture: streaming_desc.platform_arch.unwrap_or_default(),
                         os: streaming_desc.platform_os.unwrap_or_default(),
-                        variant: None,
+                        ..Default::default()
                     }),
                 }),
                 Err(err) => {
@@ -159,7 +159,7 @@ struct StreamingDescriptor {
     #[serde(rename = "streaming.mediaType")]
     media_type: String,
     #[serde(rename = "streaming.digest")]
-    digest: String,
+    digest: Digest,
     #[serde(rename = "streaming.size")]
     size: String,
     #[serde(rename = "streaming.format")]
@@ -197,7 +197,7 @@ mod tests {
         let json = json!(
         {
             "mediaType": "",
-            "digest": "",
+            "digest": "sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
             "size": 0,
             "annotations": {
                 "streaming.mediaType": "application/vnd.docker.distribution.manifest.v2+json",
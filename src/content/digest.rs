@@ -0,0 +1,215 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Error;
+
+use super::Hasher;
+
+/// Content digest in the format `<algorithm>:<hex>`, Ex. sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824
+///
+/// Only sha256 and sha512 are supported, and the encoded portion must be lowercase hex of the exact length of the
+/// algorithm's output.
+///
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Digest {
+    /// Algorithm used to compute the digest,
+    ///
+    algorithm: Algorithm,
+    /// Full digest, including the algorithm prefix,
+    ///
+    value: String,
+}
+
+/// Enumeration of supported digest algorithms,
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Algorithm {
+    Sha256,
+    Sha512,
+}
+
+impl Algorithm {
+    /// Returns the name of the algorithm as it appears in a digest,
+    ///
+    pub fn name(&self) -> &'static str {
+        match self {
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Sha512 => "sha512",
+        }
+    }
+
+    /// Returns the length of the hex encoded output of the algorithm,
+    ///
+    pub fn hex_len(&self) -> usize {
+        match self {
+            Algorithm::Sha256 => 64,
+            Algorithm::Sha512 => 128,
+        }
+    }
+}
+
+impl Digest {
+    /// Parses and validates a digest,
+    ///
+    pub fn parse(digest: impl AsRef<str>) -> Result<Self, Error> {
+        let digest = digest.as_ref();
+        let (algorithm, hex) = digest
+            .split_once(':')
+            .ok_or_else(|| Error::invalid_digest("missing algorithm"))?;
+
+        let algorithm = match algorithm {
+            "sha256" => Algorithm::Sha256,
+            "sha512" => Algorithm::Sha512,
+            _ => return Err(Error::invalid_digest("unsupported algorithm")),
+        };
+
+        if hex.len() != algorithm.hex_len() {
+            return Err(Error::invalid_digest("invalid length"));
+        }
+
+        if !hex.chars().all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c)) {
+            return Err(Error::invalid_digest("invalid encoding"));
+        }
+
+        Ok(Self {
+            algorithm,
+            value: digest.to_string(),
+        })
+    }
+
+    /// Returns the sha256 digest of content,
+    ///
+    pub fn of(content: impl AsRef<[u8]>) -> Self {
+        let mut hasher = Hasher::default();
+        hasher.update(content);
+
+        Self {
+            algorithm: Algorithm::Sha256,
+            value: hasher.finish(),
+        }
+    }
+
+    /// Returns the algorithm of the digest,
+    ///
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// Returns the hex encoded portion of the digest,
+    ///
+    pub fn hex(&self) -> &str {
+        &self.value[self.algorithm.name().len() + 1..]
+    }
+
+    /// Returns the full digest, including the algorithm,
+    ///
+    pub fn as_str(&self) -> &str {
+        &self.value
+    }
+
+    /// Returns true if the content hashes to this digest,
+    ///
+    pub fn verify(&self, content: impl AsRef<[u8]>) -> bool {
+        Hasher::verify(&self.value, content)
+    }
+}
+
+impl Default for Digest {
+    /// Returns the digest of empty content,
+    ///
+    fn default() -> Self {
+        Self::of([])
+    }
+}
+
+impl FromStr for Digest {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl TryFrom<String> for Digest {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::parse(value)
+    }
+}
+
+impl From<Digest> for String {
+    fn from(value: Digest) -> Self {
+        value.value
+    }
+}
+
+impl AsRef<str> for Digest {
+    fn as_ref(&self) -> &str {
+        &self.value
+    }
+}
+
+impl PartialEq<str> for Digest {
+    fn eq(&self, other: &str) -> bool {
+        self.value == other
+    }
+}
+
+impl PartialEq<&str> for Digest {
+    fn eq(&self, other: &&str) -> bool {
+        self.value == *other
+    }
+}
+
+impl PartialEq<Digest> for str {
+    fn eq(&self, other: &Digest) -> bool {
+        self == other.value
+    }
+}
+
+impl PartialEq<Digest> for &str {
+    fn eq(&self, other: &Digest) -> bool {
+        *self == other.value
+    }
+}
+
+impl Display for Digest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
+
+#[allow(unused_imports)]
+mod tests {
+    use super::{Algorithm, Digest};
+
+    #[test]
+    fn test_digest() {
+        let digest = Digest::parse("sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824").unwrap();
+        assert_eq!(Algorithm::Sha256, digest.algorithm());
+        assert_eq!("2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824", digest.hex());
+        assert!(digest.verify("hello"));
+        assert_eq!(digest, Digest::of("hello"));
+
+        let sha512 = "sha512:9b71d224bd62f3785d96d46ad3ea3d73319bfbc2890caadae2dff72519673ca72323c3d99ba5c11d7c7acc6e14b8c5da0c4663475c2e5c3adef46f73bcdec043";
+        assert_eq!(Algorithm::Sha512, Digest::parse(sha512).unwrap().algorithm());
+
+        // Lengths must match the algorithm
+        assert!(Digest::parse("sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b982").is_err());
+        assert!(Digest::parse("sha512:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824").is_err());
+        // Unknown algorithms, and non-hex encodings are rejected
+        assert!(Digest::parse("md5:5d41402abc4b2a76b9719d911017c592").is_err());
+        assert!(Digest::parse("sha256:2CF24DBA5FB0A30E26E83B2AC5B9E29E1B161E5C1FA7425E73043362938B9824").is_err());
+        assert!(Digest::parse("hello").is_err());
+
+        // Serializes as a plain string
+        let json = serde_json::to_string(&digest).unwrap();
+        assert_eq!(format!("\"{digest}\""), json);
+        assert_eq!(digest, serde_json::from_str::<Digest>(&json).unwrap());
+        assert!(serde_json::from_str::<Digest>("\"sha256:abc\"").is_err());
+    }
+}
@@ -0,0 +1,394 @@
+use std::collections::{BTreeSet, HashSet};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info};
+
+use crate::Error;
+
+use super::{BlobStore, Local, ManifestCache};
+
+/// Default interval between background eviction passes,
+///
+pub const DEFAULT_GC_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Content accessed more recently than this is never evicted,
+///
+/// A reader looks up content before it opens it, so this keeps eviction from removing content between the two steps.
+///
+pub const EVICTION_GRACE_PERIOD: Duration = Duration::from_secs(60);
+
+/// Size-bounded eviction for content cached by the mirror,
+///
+/// Content is evicted least recently accessed first until the total size of the cache is under the quota. References in
+/// the pin list, and all of the content they refer to, are never evicted. Evicted content is only unlinked from the
+/// filesystem, so readers that already opened it can finish reading.
+///
+#[derive(Debug, Clone)]
+pub struct Eviction {
+    /// Local blob store,
+    ///
+    blobs: BlobStore,
+    /// Manifest cache,
+    ///
+    manifests: ManifestCache,
+    /// Path to the pin list,
+    ///
+    pins: PathBuf,
+    /// Maximum size of cached content in bytes, if None the cache is unbounded,
+    ///
+    quota: Option<u64>,
+}
+
+/// Kind of cached content,
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheKind {
+    Blob,
+    Manifest,
+}
+
+/// Entry in the cache,
+///
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    /// Kind of content,
+    ///
+    pub kind: CacheKind,
+    /// Local content,
+    ///
+    pub local: Local,
+    /// Time the content was last accessed,
+    ///
+    pub accessed: SystemTime,
+    /// True if the content is protected by the pin list,
+    ///
+    pub pinned: bool,
+}
+
+/// Summary of an eviction pass,
+///
+#[derive(Debug, Clone, Default)]
+pub struct EvictionSummary {
+    /// Number of entries evicted,
+    ///
+    pub evicted: usize,
+    /// Number of bytes reclaimed,
+    ///
+    pub reclaimed: u64,
+    /// Number of bytes remaining in the cache,
+    ///
+    pub remaining: u64,
+}
+
+impl Eviction {
+    /// Returns a new eviction policy over a blob store and manifest cache,
+    ///
+    pub fn new(blobs: BlobStore, manifests: ManifestCache, pins: impl Into<PathBuf>) -> Self {
+        Self {
+            blobs,
+            manifests,
+            pins: pins.into(),
+            quota: None,
+        }
+    }
+
+    /// Sets the maximum size of cached content in bytes,
+    ///
+    pub fn with_quota(mut self, quota: u64) -> Self {
+        self.quota = Some(quota);
+        self
+    }
+
+    /// Returns the quota of this cache,
+    ///
+    pub fn quota(&self) -> Option<u64> {
+        self.quota
+    }
+
+    /// Returns the current pin list,
+    ///
+    pub async fn pins(&self) -> Result<BTreeSet<String>, Error> {
+        if !self.pins.exists() {
+            return Ok(BTreeSet::new());
+        }
+
+        let pins = tokio::fs::read(&self.pins).await?;
+        Ok(serde_json::from_slice(&pins)?)
+    }
+
+    /// Adds a reference to the pin list,
+    ///
+    /// A reference can be a digest, a `<namespace>/<repo>@<digest>` reference, or a `<namespace>/<repo>:<tag>` reference
+    /// that has been resolved by the mirror.
+    ///
+    pub async fn pin(&self, reference: impl AsRef<str>) -> Result<(), Error> {
+        let mut pins = self.pins().await?;
+        if pins.insert(reference.as_ref().to_string()) {
+            self.write_pins(&pins).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes a reference from the pin list,
+    ///
+    pub async fn unpin(&self, reference: impl AsRef<str>) -> Result<(), Error> {
+        let mut pins = self.pins().await?;
+        if pins.remove(reference.as_ref()) {
+            self.write_pins(&pins).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns all cached content, most recently accessed first,
+    ///
+    pub async fn entries(&self) -> Result<Vec<CacheEntry>, Error> {
+        let pinned = self.pinned_digests().await?;
+
+        let mut entries = vec![];
+        for (kind, store) in [
+            (CacheKind::Blob, &self.blobs),
+            (CacheKind::Manifest, self.manifests.manifests()),
+        ] {
+            for (local, accessed) in store.entries().await? {
+                entries.push(CacheEntry {
+                    kind,
+                    pinned: pinned.contains(&local.digest),
+                    local,
+                    accessed,
+                });
+            }
+        }
+
+        entries.sort_by(|a, b| b.accessed.cmp(&a.accessed));
+        Ok(entries)
+    }
+
+    /// Evicts unpinned content until the cache is under quota,
+    ///
+    pub async fn gc(&self) -> Result<EvictionSummary, Error> {
+        match self.quota {
+            Some(quota) => self.evict(quota, Some(EVICTION_GRACE_PERIOD)).await,
+            None => Ok(EvictionSummary {
+                remaining: self.size().await?,
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// Evicts all unpinned content,
+    ///
+    pub async fn purge(&self) -> Result<EvictionSummary, Error> {
+        self.evict(0, None).await
+    }
+
+    /// Returns the total size of cached content in bytes,
+    ///
+    pub async fn size(&self) -> Result<u64, Error> {
+        Ok(self.entries().await?.iter().map(|e| e.local.size).sum())
+    }
+
+    /// Starts running eviction in the background, returns None if there is no quota or no runtime to spawn on,
+    ///
+    pub fn start(self, interval: Duration) -> Option<JoinHandle<()>> {
+        self.quota?;
+
+        let handle = tokio::runtime::Handle::try_current().ok()?;
+        Some(handle.spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+
+                match self.gc().await {
+                    Ok(summary) if summary.evicted > 0 => info!(
+                        "Evicted {} entries, reclaimed {} bytes, {} bytes remaining",
+                        summary.evicted, summary.reclaimed, summary.remaining
+                    ),
+                    Ok(_) => {}
+                    Err(err) => error!("Could not run eviction, {err}"),
+                }
+            }
+        }))
+    }
+
+    /// Evicts least recently accessed content until the total size is under the target,
+    ///
+    /// If a grace period is passed, content accessed within it is skipped,
+    ///
+    async fn evict(&self, target: u64, grace: Option<Duration>) -> Result<EvictionSummary, Error> {
+        let mut entries = self.entries().await?;
+        entries.reverse();
+
+        let mut summary = EvictionSummary {
+            remaining: entries.iter().map(|e| e.local.size).sum(),
+            ..Default::default()
+        };
+
+        for entry in entries {
+            if summary.remaining <= target {
+                break;
+            }
+
+            let recent = grace
+                .map(|grace| !matches!(entry.accessed.elapsed(), Ok(e) if e >= grace))
+                .unwrap_or_default();
+            if entry.pinned || recent {
+                continue;
+            }
+
+            let store = match entry.kind {
+                CacheKind::Blob => &self.blobs,
+                CacheKind::Manifest => self.manifests.manifests(),
+            };
+            store.remove(&entry.local.digest).await?;
+
+            debug!("Evicted {}", entry.local.digest);
+            summary.evicted += 1;
+            summary.reclaimed += entry.local.size;
+            summary.remaining -= entry.local.size;
+        }
+
+        Ok(summary)
+    }
+
+    /// Returns the digests of all content protected by the pin list,
+    ///
+    /// Manifests are walked so that the config, layers, and child manifests of a pinned image are also protected,
+    ///
+    async fn pinned_digests(&self) -> Result<HashSet<String>, Error> {
+        let mut pending = vec![];
+        for reference in self.pins().await? {
+            if let Some((_, digest)) = reference.rsplit_once('@') {
+                pending.push(digest.to_string());
+            } else if self.blobs.path(&reference).is_some() {
+                pending.push(reference);
+            } else if let Some(manifest) = self.manifests.get_tag(&reference, true).await {
+                pending.push(manifest.digest);
+            }
+        }
+
+        let mut pinned = HashSet::new();
+        while let Some(digest) = pending.pop() {
+            if !pinned.insert(digest.clone()) {
+                continue;
+            }
+
+            if let Some(manifest) = self.manifests.get_digest(&digest).await {
+                pending.extend(referenced_digests(&manifest.bytes));
+            }
+        }
+
+        Ok(pinned)
+    }
+
+    /// Writes the pin list,
+    ///
+    async fn write_pins(&self, pins: &BTreeSet<String>) -> Result<(), Error> {
+        if let Some(parent) = self.pins.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let partial = self.pins.with_extension("partial");
+        tokio::fs::write(&partial, serde_json::to_vec_pretty(pins)?).await?;
+        tokio::fs::rename(&partial, &self.pins).await?;
+        Ok(())
+    }
+}
+
+/// Parses a size in bytes w/ an optional binary unit suffix, Ex. 512M, 10G, 1TiB
+///
+pub fn parse_size(size: impl AsRef<str>) -> Option<u64> {
+    let size = size.as_ref().trim();
+    let split = size
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(size.len());
+    let (value, unit) = size.split_at(split);
+    let value = value.parse::<u64>().ok()?;
+
+    let shift = match unit.trim().trim_end_matches("iB").trim_end_matches('B') {
+        "" => 0,
+        "K" | "k" => 10,
+        "M" | "m" => 20,
+        "G" | "g" => 30,
+        "T" | "t" => 40,
+        _ => return None,
+    };
+
+    value.checked_mul(1 << shift)
+}
+
+/// Returns the digests of content a manifest refers to,
+///
+fn referenced_digests(manifest: &[u8]) -> Vec<String> {
+    let manifest = match serde_json::from_slice::<serde_json::Value>(manifest) {
+        Ok(manifest) => manifest,
+        Err(_) => return vec![],
+    };
+
+    let digest = |v: &serde_json::Value| v["digest"].as_str().map(str::to_string);
+
+    let mut digests = vec![];
+    digests.extend(digest(&manifest["config"]));
+    for field in ["layers", "manifests", "blobs"] {
+        if let Some(descriptors) = manifest[field].as_array() {
+            digests.extend(descriptors.iter().filter_map(digest));
+        }
+    }
+
+    digests
+}
+
+#[allow(unused_imports)]
+mod tests {
+    use super::{parse_size, Eviction};
+    use crate::{BlobStore, ManifestCache};
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(Some(100), parse_size("100"));
+        assert_eq!(Some(512 << 20), parse_size("512M"));
+        assert_eq!(Some(10 << 30), parse_size("10G"));
+        assert_eq!(Some(1 << 40), parse_size("1TiB"));
+        assert_eq!(None, parse_size("10X"));
+    }
+
+    #[tokio::test]
+    async fn test_eviction() {
+        let root = std::path::PathBuf::from(".test_eviction");
+        let blobs = BlobStore::new(root.join("blobs"));
+        let manifests = ManifestCache::new(root.join("manifests"));
+        let eviction = Eviction::new(blobs.clone(), manifests.clone(), root.join("pins.json"));
+
+        let hello = "sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+        let world = "sha256:486ea46224d1bb4fb680f34f7c9ad96a8f24ec88be73ea8e5a6c65260e9cb8a7";
+        blobs.put(hello, b"hello").await.unwrap();
+        blobs.put(world, b"world").await.unwrap();
+
+        let manifest = format!(r#"{{"schemaVersion":2,"layers":[{{"digest":"{hello}","size":5}}]}}"#);
+        let mut hasher = super::super::Hasher::default();
+        hasher.update(&manifest);
+        let manifest_digest = hasher.finish();
+        let key = ManifestCache::key("test.azurecr.io", "library/test", "latest", None::<String>);
+        manifests
+            .put(Some(&key), &manifest_digest, "application/vnd.oci.image.manifest.v1+json", &manifest, None)
+            .await
+            .unwrap();
+
+        eviction.pin(&key).await.unwrap();
+        assert_eq!(3, eviction.entries().await.unwrap().len());
+        assert_eq!(2, eviction.entries().await.unwrap().iter().filter(|e| e.pinned).count());
+
+        let summary = eviction.purge().await.unwrap();
+        assert_eq!(1, summary.evicted);
+        assert!(blobs.get(world).await.is_none());
+        assert!(blobs.get(hello).await.is_some());
+
+        eviction.unpin(&key).await.unwrap();
+        eviction.purge().await.unwrap();
+        assert!(eviction.entries().await.unwrap().is_empty());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
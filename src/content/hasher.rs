@@ -0,0 +1,136 @@
+use hyper::body::HttpBody;
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::Error;
+
+/// Incremental hasher for content digests,
+///
+/// The algorithm is picked from the digest the content is expected to match,
+///
+pub enum Hasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+}
+
+impl Hasher {
+    /// Returns a hasher for the algorithm of the digest, returns None if the algorithm is not supported,
+    ///
+    pub fn for_digest(digest: impl AsRef<str>) -> Option<Self> {
+        match digest.as_ref().split_once(':') {
+            Some(("sha256", _)) => Some(Hasher::Sha256(Sha256::new())),
+            Some(("sha512", _)) => Some(Hasher::Sha512(Sha512::new())),
+            _ => None,
+        }
+    }
+
+    /// Updates the hasher w/ the next chunk of content,
+    ///
+    pub fn update(&mut self, data: impl AsRef<[u8]>) {
+        match self {
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Sha512(hasher) => hasher.update(data),
+        }
+    }
+
+    /// Consumes the hasher and returns the digest in the format `<algorithm>:<hex>`,
+    ///
+    pub fn finish(self) -> String {
+        match self {
+            Hasher::Sha256(hasher) => format!("sha256:{:x}", hasher.finalize()),
+            Hasher::Sha512(hasher) => format!("sha512:{:x}", hasher.finalize()),
+        }
+    }
+
+    /// Returns true if the content hashes to the digest,
+    ///
+    pub fn verify(digest: impl AsRef<str>, content: impl AsRef<[u8]>) -> bool {
+        if let Some(mut hasher) = Self::for_digest(digest.as_ref()) {
+            hasher.update(content);
+            hasher.finish() == digest.as_ref()
+        } else {
+            false
+        }
+    }
+}
+
+/// Reads a body and verifies it against a digest and an expected size, returns the content if it matches,
+///
+/// Reading stops as soon as the body is larger than the expected size, so an oversized response is never fully buffered.
+///
+pub async fn read_verified(mut body: hyper::Body, digest: impl AsRef<str>, size: Option<u64>) -> Result<Vec<u8>, Error> {
+    let digest = digest.as_ref();
+    let mut hasher =
+        Hasher::for_digest(digest).ok_or_else(|| Error::invalid_operation("unsupported digest format"))?;
+
+    let mut content = Vec::with_capacity(size.unwrap_or_default().min(1 << 20) as usize);
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        hasher.update(&chunk);
+        content.extend_from_slice(&chunk);
+
+        match size {
+            Some(size) if content.len() as u64 > size => {
+                return Err(Error::size_mismatch(size, content.len() as u64));
+            }
+            _ => {}
+        }
+    }
+
+    match size {
+        Some(size) if content.len() as u64 != size => Err(Error::size_mismatch(size, content.len() as u64)),
+        _ => {
+            let actual = hasher.finish();
+            if actual == digest {
+                Ok(content)
+            } else {
+                Err(Error::digest_mismatch(digest, actual))
+            }
+        }
+    }
+}
+
+impl Default for Hasher {
+    fn default() -> Self {
+        Hasher::Sha256(Sha256::new())
+    }
+}
+
+#[allow(unused_imports)]
+mod tests {
+    use super::{read_verified, Hasher};
+    use crate::error::ErrorCategory;
+
+    #[test]
+    fn test_hasher() {
+        assert!(Hasher::verify(
+            "sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            b""
+        ));
+        assert!(!Hasher::verify(
+            "sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            b"hello"
+        ));
+        assert!(Hasher::for_digest("md5:abc").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_read_verified() {
+        let digest = "sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+        let sha512 = "sha512:9b71d224bd62f3785d96d46ad3ea3d73319bfbc2890caadae2dff72519673ca72323c3d99ba5c11d7c7acc6e14b8c5da0c4663475c2e5c3adef46f73bcdec043";
+
+        let content = read_verified(hyper::Body::from("hello"), digest, Some(5)).await.unwrap();
+        assert_eq!(b"hello", content.as_slice());
+        assert!(read_verified(hyper::Body::from("hello"), sha512, None).await.is_ok());
+
+        let err = read_verified(hyper::Body::from("hellO"), digest, Some(5)).await.unwrap_err();
+        assert!(matches!(err.category(), ErrorCategory::DigestMismatch { .. }));
+
+        let err = read_verified(hyper::Body::from("hello!"), digest, Some(5)).await.unwrap_err();
+        assert!(matches!(err.category(), ErrorCategory::SizeMismatch { expected: 5, actual: 6 }));
+
+        let err = read_verified(hyper::Body::from("hello"), digest, Some(6)).await.unwrap_err();
+        assert!(err.is_content_mismatch());
+
+        assert!(read_verified(hyper::Body::from("hello"), "md5:abc", None).await.is_err());
+    }
+}
@@ -1,9 +1,11 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 use specs::{Component, VecStorage};
 
 use crate::Descriptor;
 
-/// Struct for an image manifest,
+/// Struct for an image index,
 /// 
 #[derive(Component, Debug, Default, Clone, Serialize, Deserialize)]
 #[storage(VecStorage)]
@@ -12,13 +14,25 @@ pub struct ImageIndex {
     /// 
     #[serde(rename = "schemaVersion")]
     pub schema_version: usize,
-    /// Media type, for this manifest ;ost
+    /// Media type, for this index it should be application/vnd.oci.image.index.v1+json
     /// 
-    #[serde(rename = "mediaType")]
+    #[serde(rename = "mediaType", default, skip_serializing_if = "String::is_empty")]
     pub media_type: String,
+    /// Type of artifact when the index is used for an artifact,
+    /// 
+    #[serde(rename = "artifactType", skip_serializing_if = "Option::is_none")]
+    pub artifact_type: Option<String>,
     /// List of manifests contained within this index
     /// 
-    pub manifests: Vec<Descriptor> 
+    pub manifests: Vec<Descriptor>,
+    /// Indicates a relationship to the descriptor,
+    /// 
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<Descriptor>,
+    /// Optional, labels
+    /// 
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<BTreeMap<String, String>>,
 }
 
 /// Docker manifest list media type,
@@ -27,4 +41,99 @@ pub const DOCKER_MANIFEST_LIST: &'static str = "application/vnd.docker.distribut
 
 /// OCI Image index mediat type,
 /// 
-pub const OCI_IMAGE_INDEX: &'static str = "application/vnd.oci.image.index.v1+json";
\ No newline at end of file
+pub const OCI_IMAGE_INDEX: &'static str = "application/vnd.oci.image.index.v1+json";
+
+#[allow(unused_imports)]
+mod tests {
+    use super::ImageIndex;
+
+    /// Asserts that a document is unchanged after a round-trip through an image index,
+    /// 
+    fn assert_round_trip(document: &str) -> ImageIndex {
+        let expected = serde_json::from_str::<serde_json::Value>(document).unwrap();
+        let index = serde_json::from_str::<ImageIndex>(document).unwrap();
+        assert_eq!(expected, serde_json::to_value(&index).unwrap());
+        index
+    }
+
+    #[test]
+    fn test_image_index_round_trip() {
+        // Example image index from the OCI image-spec v1.1
+        let index = assert_round_trip(
+            r#"{
+                "schemaVersion": 2,
+                "mediaType": "application/vnd.oci.image.index.v1+json",
+                "manifests": [
+                    {
+                        "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                        "size": 7143,
+                        "digest": "sha256:e692418e4cbaf90ca69d05a66403747baa33ee08806650b51fab815ad7fc331f",
+                        "platform": {
+                            "architecture": "ppc64le",
+                            "os": "linux"
+                        }
+                    },
+                    {
+                        "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                        "size": 7682,
+                        "digest": "sha256:5b0bcabd1ed22e9fb1310cf6c2dec7cdef19f0ad69efa1f392e94a4333501270",
+                        "platform": {
+                            "architecture": "amd64",
+                            "os": "linux"
+                        }
+                    }
+                ],
+                "annotations": {
+                    "com.example.key1": "value1",
+                    "com.example.key2": "value2"
+                }
+            }"#,
+        );
+        assert_eq!(2, index.manifests.len());
+        assert_eq!(2, index.annotations.map(|a| a.len()).unwrap_or_default());
+
+        // Index used for an artifact, w/ a subject and windows platforms
+        let index = assert_round_trip(
+            r#"{
+                "schemaVersion": 2,
+                "mediaType": "application/vnd.oci.image.index.v1+json",
+                "artifactType": "application/vnd.example.sbom.v1",
+                "manifests": [
+                    {
+                        "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                        "artifactType": "application/vnd.example.sbom.v1",
+                        "size": 1024,
+                        "digest": "sha256:e692418e4cbaf90ca69d05a66403747baa33ee08806650b51fab815ad7fc331f",
+                        "platform": {
+                            "architecture": "amd64",
+                            "os": "windows",
+                            "os.version": "10.0.17763.1040",
+                            "os.features": ["win32k"]
+                        }
+                    },
+                    {
+                        "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                        "size": 1025,
+                        "digest": "sha256:5b0bcabd1ed22e9fb1310cf6c2dec7cdef19f0ad69efa1f392e94a4333501270",
+                        "platform": {
+                            "architecture": "arm",
+                            "os": "linux",
+                            "variant": "v7"
+                        }
+                    }
+                ],
+                "subject": {
+                    "mediaType": "application/vnd.oci.image.index.v1+json",
+                    "digest": "sha256:b5b2b2c507a0944348e0303114d8d93aaaa081732b86451d9bce1f432a537bc7",
+                    "size": 7023
+                }
+            }"#,
+        );
+        assert_eq!(Some("application/vnd.example.sbom.v1"), index.artifact_type.as_deref());
+        assert!(index.subject.is_some());
+
+        let platform = index.manifests[0].platform.as_ref().unwrap();
+        assert_eq!(Some("10.0.17763.1040"), platform.os_version.as_deref());
+        assert_eq!(Some(vec!["win32k".to_string()]), platform.os_features);
+    }
+}
\ No newline at end of file
@@ -10,12 +10,18 @@ use super::Descriptor;
 #[derive(Component, Debug, Default, Clone, Serialize, Deserialize)]
 #[storage(VecStorage)]
 pub struct ImageManifest {
+    /// Schema version of this manifest, must be 2
+    /// 
     #[serde(rename = "schemaVersion")]
-    pub schema_versin: usize,
-    /// Media type, for this manifest it should be application/vnd.oci.artifact.manifest.v1+json
+    pub schema_version: usize,
+    /// Media type, for this manifest it should be application/vnd.oci.image.manifest.v1+json
     /// 
-    #[serde(rename = "mediaType")]
+    #[serde(rename = "mediaType", default, skip_serializing_if = "String::is_empty")]
     pub media_type: String,
+    /// Type of artifact when the manifest is used for an artifact, if the config is not the empty descriptor this is the config media type,
+    /// 
+    #[serde(rename = "artifactType", skip_serializing_if = "Option::is_none")]
+    pub artifact_type: Option<String>,
     /// Descriptor pointing to the config for this image,
     /// 
     pub config: Descriptor, 
@@ -43,3 +49,104 @@ pub const DOCKER_V1_MANIFEST: &'static str  = "application/vnd.docker.distributi
 /// Docker V2 manifest media type,
 /// 
 pub const DOCKER_V2_MANIFEST: &'static str  = "application/vnd.docker.distribution.manifest.v2+json";
+
+#[allow(unused_imports)]
+mod tests {
+    use super::ImageManifest;
+
+    /// Asserts that a document is unchanged after a round-trip through an image manifest,
+    /// 
+    fn assert_round_trip(document: &str) -> ImageManifest {
+        let expected = serde_json::from_str::<serde_json::Value>(document).unwrap();
+        let manifest = serde_json::from_str::<ImageManifest>(document).unwrap();
+        assert_eq!(expected, serde_json::to_value(&manifest).unwrap());
+        manifest
+    }
+
+    #[test]
+    fn test_image_manifest_round_trip() {
+        // Example manifest from the OCI image-spec v1.1
+        let manifest = assert_round_trip(
+            r#"{
+                "schemaVersion": 2,
+                "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                "config": {
+                    "mediaType": "application/vnd.oci.image.config.v1+json",
+                    "digest": "sha256:b5b2b2c507a0944348e0303114d8d93aaaa081732b86451d9bce1f432a537bc7",
+                    "size": 7023
+                },
+                "layers": [
+                    {
+                        "mediaType": "application/vnd.oci.image.layer.v1.tar+gzip",
+                        "digest": "sha256:9834876dcfb05cb167a5c24953eba58c4ac89b1adf57f28f2f9d09af107ee8f0",
+                        "size": 32654
+                    },
+                    {
+                        "mediaType": "application/vnd.oci.image.layer.v1.tar+gzip",
+                        "digest": "sha256:3c3a4604a545cdc127456d94e421cd355bca5b528f4a9c1905b15da2eb4a4c6b",
+                        "size": 16724
+                    },
+                    {
+                        "mediaType": "application/vnd.oci.image.layer.v1.tar+gzip",
+                        "digest": "sha256:ec4b8955958665577945c89419d1af06b5f7636b4ac3da7f12184802ad867736",
+                        "size": 73109
+                    }
+                ],
+                "subject": {
+                    "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                    "digest": "sha256:5b0bcabd1ed22e9fb1310cf6c2dec7cdef19f0ad69efa1f392e94a4333501270",
+                    "size": 7682
+                },
+                "annotations": {
+                    "com.example.key1": "value1",
+                    "com.example.key2": "value2"
+                }
+            }"#,
+        );
+        assert_eq!(2, manifest.schema_version);
+        assert_eq!(3, manifest.layers.len());
+        assert!(manifest.subject.is_some());
+
+        // Example artifact from the OCI image-spec v1.1 artifact guidance, w/ the empty config
+        let artifact = assert_round_trip(
+            r#"{
+                "schemaVersion": 2,
+                "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                "artifactType": "application/vnd.example+type",
+                "config": {
+                    "mediaType": "application/vnd.oci.empty.v1+json",
+                    "digest": "sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a",
+                    "size": 2,
+                    "data": "e30="
+                },
+                "layers": [
+                    {
+                        "mediaType": "application/vnd.oci.empty.v1+json",
+                        "digest": "sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a",
+                        "size": 2
+                    }
+                ],
+                "annotations": {
+                    "oci.opencontainers.image.created": "2023-01-02T03:04:05Z",
+                    "com.example.data": "payload"
+                }
+            }"#,
+        );
+        assert_eq!(Some("application/vnd.example+type"), artifact.artifact_type.as_deref());
+        assert_eq!(Some("e30="), artifact.config.data.as_deref());
+
+        // The media type is optional
+        let manifest = assert_round_trip(
+            r#"{
+                "schemaVersion": 2,
+                "config": {
+                    "mediaType": "application/vnd.oci.image.config.v1+json",
+                    "digest": "sha256:b5b2b2c507a0944348e0303114d8d93aaaa081732b86451d9bce1f432a537bc7",
+                    "size": 7023
+                },
+                "layers": []
+            }"#,
+        );
+        assert!(manifest.media_type.is_empty());
+    }
+}
@@ -3,11 +3,17 @@ use std::path::PathBuf;
 use specs::{Component, VecStorage};
 
 /// Component for local content,
-/// 
-#[derive(Component)]
+///
+#[derive(Component, Debug, Clone)]
 #[storage(VecStorage)]
 pub struct Local {
     /// Path to local content,
-    /// 
-    pub path: PathBuf
-}
\ No newline at end of file
+    ///
+    pub path: PathBuf,
+    /// Digest of the local content,
+    ///
+    pub digest: String,
+    /// Size of the local content in bytes,
+    ///
+    pub size: u64,
+}
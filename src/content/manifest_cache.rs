@@ -0,0 +1,373 @@
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::Error;
+
+use super::{BlobStore, Hasher};
+
+/// Default amount of time a resolved tag is considered fresh,
+///
+pub const DEFAULT_TAG_TTL: Duration = Duration::from_secs(300);
+
+/// Cache for manifests resolved through the proxy,
+///
+/// Manifests are stored by digest and never expire. Tags are stored as a mapping from `<namespace>/<repo>:<tag>` to
+/// the digest the upstream resolved it to, and are only considered fresh for the configured ttl. When stale-if-error is
+/// enabled, expired tags can still be served if the upstream is unavailable.
+///
+#[derive(Debug, Clone)]
+pub struct ManifestCache {
+    /// Store for manifest content,
+    ///
+    manifests: BlobStore,
+    /// Directory tag entries are stored in,
+    ///
+    tags: PathBuf,
+    /// Amount of time a tag is considered fresh,
+    ///
+    ttl: Duration,
+    /// If true, expired tags can be served when the upstream returns an error,
+    ///
+    stale_if_error: bool,
+}
+
+/// Manifest content read from the cache,
+///
+#[derive(Debug, Clone)]
+pub struct CachedManifest {
+    /// Digest of the manifest,
+    ///
+    pub digest: String,
+    /// Media type of the manifest,
+    ///
+    pub media_type: String,
+    /// Manifest content,
+    ///
+    pub bytes: Vec<u8>,
+    /// True if this manifest was resolved from an expired tag,
+    ///
+    pub stale: bool,
+}
+
+/// Tag entry stored on disk,
+///
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TagEntry {
+    /// Cache key of this tag,
+    ///
+    key: String,
+    /// Digest the tag resolved to,
+    ///
+    digest: String,
+    /// Media type of the manifest the tag resolved to,
+    ///
+    media_type: String,
+    /// Seconds since the unix epoch when the tag was resolved,
+    ///
+    resolved_at: u64,
+}
+
+/// Manifest metadata stored next to manifest content,
+///
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ManifestEntry {
+    /// Media type of the manifest,
+    ///
+    media_type: String,
+}
+
+impl ManifestCache {
+    /// Returns a manifest cache rooted at the given directory,
+    ///
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        let root = root.into();
+        Self {
+            manifests: BlobStore::new(&root),
+            tags: root.join("tags"),
+            ttl: DEFAULT_TAG_TTL,
+            stale_if_error: false,
+        }
+    }
+
+    /// Sets the amount of time a resolved tag is considered fresh,
+    ///
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Sets whether expired tags can be served when the upstream returns an error,
+    ///
+    pub fn with_stale_if_error(mut self, stale_if_error: bool) -> Self {
+        self.stale_if_error = stale_if_error;
+        self
+    }
+
+    /// Returns true if expired tags can be served when the upstream returns an error,
+    ///
+    pub fn stale_if_error(&self) -> bool {
+        self.stale_if_error
+    }
+
+    /// Returns the store manifest content is kept in,
+    ///
+    pub fn manifests(&self) -> &BlobStore {
+        &self.manifests
+    }
+
+    /// Returns the cache key for a tag,
+    ///
+    /// If the request was for a specific streaming format, the format is included in the key since the tag will resolve
+    /// to a different manifest.
+    ///
+    pub fn key(
+        namespace: impl AsRef<str>,
+        repo: impl AsRef<str>,
+        reference: impl AsRef<str>,
+        format: Option<impl AsRef<str>>,
+    ) -> String {
+        let key = format!(
+            "{}/{}:{}",
+            namespace.as_ref(),
+            repo.as_ref(),
+            reference.as_ref()
+        );
+
+        match format {
+            Some(format) => format!("{key}#{}", format.as_ref()),
+            None => key,
+        }
+    }
+
+    /// Returns a cached manifest by digest,
+    ///
+    pub async fn get_digest(&self, digest: impl AsRef<str>) -> Option<CachedManifest> {
+        let local = self.manifests.get(digest.as_ref()).await?;
+        let entry = tokio::fs::read(local.path.with_extension("json")).await.ok()?;
+        let entry = serde_json::from_slice::<ManifestEntry>(&entry).ok()?;
+        let bytes = tokio::fs::read(&local.path).await.ok()?;
+
+        Some(CachedManifest {
+            digest: local.digest,
+            media_type: entry.media_type,
+            bytes,
+            stale: false,
+        })
+    }
+
+    /// Returns a cached manifest by tag key, if allow_stale is false only fresh tags are returned,
+    ///
+    pub async fn get_tag(&self, key: impl AsRef<str>, allow_stale: bool) -> Option<CachedManifest> {
+        let entry = tokio::fs::read(self.tag_path(key.as_ref())).await.ok()?;
+        let entry = serde_json::from_slice::<TagEntry>(&entry).ok()?;
+
+        let stale = now().ok()?.saturating_sub(entry.resolved_at) >= self.ttl.as_secs();
+        if stale && !allow_stale {
+            debug!("Tag {} has expired", entry.key);
+            return None;
+        }
+
+        self.get_digest(&entry.digest).await.map(|mut m| {
+            m.stale = stale;
+            m
+        })
+    }
+
+    /// Verifies and stores a manifest by digest, if a tag key is passed the tag is also updated,
+    ///
+    /// If a source is passed, it is recorded as a repository the manifest was fetched from, see `CacheIndex::source`.
+    ///
+    pub async fn put(
+        &self,
+        key: Option<impl AsRef<str>>,
+        digest: impl AsRef<str>,
+        media_type: impl AsRef<str>,
+        bytes: impl AsRef<[u8]>,
+        source: Option<&str>,
+    ) -> Result<(), Error> {
+        let local = self
+            .manifests
+            .put_with(digest.as_ref(), bytes, Some(media_type.as_ref()), source)
+            .await?;
+
+        let entry = ManifestEntry {
+            media_type: media_type.as_ref().to_string(),
+        };
+        tokio::fs::write(local.path.with_extension("json"), serde_json::to_vec(&entry)?).await?;
+
+        if let Some(key) = key {
+            self.put_tag(key, digest, media_type).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Stores the digest a tag was resolved to,
+    ///
+    pub async fn put_tag(
+        &self,
+        key: impl AsRef<str>,
+        digest: impl AsRef<str>,
+        media_type: impl AsRef<str>,
+    ) -> Result<(), Error> {
+        let entry = TagEntry {
+            key: key.as_ref().to_string(),
+            digest: digest.as_ref().to_string(),
+            media_type: media_type.as_ref().to_string(),
+            resolved_at: now()?,
+        };
+
+        tokio::fs::create_dir_all(&self.tags).await?;
+        tokio::fs::write(self.tag_path(key.as_ref()), serde_json::to_vec(&entry)?).await?;
+
+        debug!("Cached tag {} -> {}", entry.key, entry.digest);
+        Ok(())
+    }
+
+    /// Removes cache entries for a reference in a repository that was pushed or deleted, returns the number of tags removed,
+    ///
+    /// If the reference is a tag, the tag is removed for every streaming format. If the reference is a digest, the manifest
+    /// content is removed along w/ every tag in the repository that resolved to it.
+    ///
+    pub async fn invalidate(
+        &self,
+        namespace: impl AsRef<str>,
+        repo: impl AsRef<str>,
+        reference: impl AsRef<str>,
+    ) -> Result<usize, Error> {
+        let reference = reference.as_ref();
+        let is_digest = self.manifests.path(reference).is_some();
+        let prefix = Self::key(namespace, repo, "", None::<String>);
+
+        let mut removed = 0;
+        if self.tags.is_dir() {
+            let mut entries = tokio::fs::read_dir(&self.tags).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let tag = match tokio::fs::read(entry.path())
+                    .await
+                    .ok()
+                    .and_then(|t| serde_json::from_slice::<TagEntry>(&t).ok())
+                {
+                    Some(tag) => tag,
+                    None => continue,
+                };
+
+                // Keys for a streaming format are suffixed w/ `#<format>`
+                let key = tag.key.split_once('#').map(|(k, _)| k).unwrap_or(&tag.key);
+                let matches = match key.strip_prefix(&prefix) {
+                    Some(_) if is_digest => tag.digest == reference,
+                    Some(tag) => tag == reference,
+                    None => false,
+                };
+
+                if matches {
+                    tokio::fs::remove_file(entry.path()).await?;
+                    debug!("Invalidated tag {}", tag.key);
+                    removed += 1;
+                }
+            }
+        }
+
+        if is_digest {
+            self.manifests.remove(reference).await?;
+            debug!("Invalidated manifest {reference}");
+        }
+
+        Ok(removed)
+    }
+
+    /// Returns the path a tag entry is stored at,
+    ///
+    /// The key is hashed since it contains the namespace which is user input,
+    ///
+    fn tag_path(&self, key: &str) -> PathBuf {
+        let mut hasher = Hasher::default();
+        hasher.update(key);
+        let name = hasher.finish();
+        let name = name.trim_start_matches("sha256:");
+
+        self.tags.join(format!("{name}.json"))
+    }
+}
+
+impl CachedManifest {
+    /// Returns true if the media type of this manifest is accepted by the value of an accept header,
+    ///
+    pub fn accepted_by(&self, accept: Option<&str>) -> bool {
+        match accept {
+            Some(accept) => accept
+                .split(',')
+                .map(|a| a.split(';').next().unwrap_or_default().trim())
+                .any(|a| a == "*/*" || a == self.media_type),
+            None => true,
+        }
+    }
+}
+
+/// Returns the current time in seconds since the unix epoch,
+///
+fn now() -> Result<u64, Error> {
+    Ok(SystemTime::UNIX_EPOCH.elapsed()?.as_secs())
+}
+
+#[allow(unused_imports)]
+mod tests {
+    use std::time::Duration;
+
+    use super::ManifestCache;
+
+    #[tokio::test]
+    async fn test_manifest_cache() {
+        let cache = ManifestCache::new(".test_manifest_cache");
+        let digest = "sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+        let key = ManifestCache::key("test.azurecr.io", "library/test", "latest", None::<String>);
+
+        assert!(cache.get_tag(&key, true).await.is_none());
+
+        cache
+            .put(
+                Some(&key),
+                digest,
+                "application/vnd.oci.image.manifest.v1+json",
+                b"hello",
+                Some("test.azurecr.io/library/test"),
+            )
+            .await
+            .expect("should cache manifest");
+        assert!(cache
+            .manifests()
+            .index()
+            .get(digest)
+            .map(|e| e.sources.contains("test.azurecr.io/library/test"))
+            .unwrap_or_default());
+
+        let cached = cache.get_tag(&key, false).await.expect("should be fresh");
+        assert_eq!(digest, cached.digest);
+        assert_eq!(b"hello".to_vec(), cached.bytes);
+        assert!(cached.accepted_by(Some("application/vnd.oci.image.manifest.v1+json; q=0.5, application/json")));
+        assert!(!cached.accepted_by(Some("application/vnd.docker.distribution.manifest.v2+json")));
+
+        let expired = cache.clone().with_ttl(Duration::from_secs(0));
+        assert!(expired.get_tag(&key, false).await.is_none());
+        assert!(expired.get_tag(&key, true).await.expect("should be stale").stale);
+        assert!(expired.get_digest(digest).await.is_some());
+
+        // Pushing a tag invalidates it for every streaming format
+        let streaming = ManifestCache::key("test.azurecr.io", "library/test", "latest", Some("overlaybd"));
+        cache.put_tag(&streaming, digest, "application/vnd.oci.image.manifest.v1+json").await.unwrap();
+        assert_eq!(2, cache.invalidate("test.azurecr.io", "library/test", "latest").await.unwrap());
+        assert!(cache.get_tag(&key, true).await.is_none());
+        assert!(cache.get_tag(&streaming, true).await.is_none());
+
+        // Deleting a digest invalidates the manifest and the tags that resolved to it
+        cache.put_tag(&key, digest, "application/vnd.oci.image.manifest.v1+json").await.unwrap();
+        assert_eq!(0, cache.invalidate("test.azurecr.io", "library/other", digest).await.unwrap());
+        assert_eq!(1, cache.invalidate("test.azurecr.io", "library/test", digest).await.unwrap());
+        assert!(cache.get_digest(digest).await.is_none());
+
+        std::fs::remove_dir_all(".test_manifest_cache").unwrap();
+    }
+}
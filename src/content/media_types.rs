@@ -0,0 +1,209 @@
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+use super::artifact_manifest::consts::{OCI_ARTIFACTS_MANIFEST_MEDIA_TYPE, ORAS_ARTIFACTS_MANIFEST_MEDIA_TYPE};
+use super::image_index::{DOCKER_MANIFEST_LIST, OCI_IMAGE_INDEX};
+use super::image_manifest::{DOCKER_V1_MANIFEST, DOCKER_V2_MANIFEST, OCI_IMAGE_MANIFEST};
+
+/// Media types registered at runtime, these take precedence over the built-in media types,
+///
+static REGISTERED: RwLock<BTreeMap<String, MediaTypeKind>> = RwLock::new(BTreeMap::new());
+
+/// Built-in media types, must be lowercase w/o parameters,
+///
+const KNOWN_MEDIA_TYPES: &[(&str, MediaTypeKind)] = &[
+    // Indexes
+    (OCI_IMAGE_INDEX, MediaTypeKind::Index),
+    (DOCKER_MANIFEST_LIST, MediaTypeKind::Index),
+    // Manifests
+    (OCI_IMAGE_MANIFEST, MediaTypeKind::Manifest),
+    (DOCKER_V1_MANIFEST, MediaTypeKind::Manifest),
+    ("application/vnd.docker.distribution.manifest.v1+prettyjws", MediaTypeKind::Manifest),
+    (DOCKER_V2_MANIFEST, MediaTypeKind::Manifest),
+    (OCI_ARTIFACTS_MANIFEST_MEDIA_TYPE, MediaTypeKind::Manifest),
+    (ORAS_ARTIFACTS_MANIFEST_MEDIA_TYPE, MediaTypeKind::Manifest),
+    // Configs
+    ("application/vnd.oci.image.config.v1+json", MediaTypeKind::Config),
+    ("application/vnd.oci.empty.v1+json", MediaTypeKind::Config),
+    ("application/vnd.docker.container.image.v1+json", MediaTypeKind::Config),
+    ("application/vnd.docker.plugin.v1+json", MediaTypeKind::Config),
+    ("application/vnd.cncf.helm.config.v1+json", MediaTypeKind::Config),
+    ("application/vnd.wasm.config.v0+json", MediaTypeKind::Config),
+    ("application/vnd.module.wasm.config.v1+json", MediaTypeKind::Config),
+    ("application/vnd.dev.cosign.simplesigning.v1+json", MediaTypeKind::Config),
+    // Layers
+    ("application/vnd.oci.image.layer.v1.tar", MediaTypeKind::Layer),
+    ("application/vnd.oci.image.layer.v1.tar+gzip", MediaTypeKind::Layer),
+    ("application/vnd.oci.image.layer.v1.tar+zstd", MediaTypeKind::Layer),
+    ("application/vnd.oci.image.layer.nondistributable.v1.tar", MediaTypeKind::Layer),
+    ("application/vnd.oci.image.layer.nondistributable.v1.tar+gzip", MediaTypeKind::Layer),
+    ("application/vnd.oci.image.layer.nondistributable.v1.tar+zstd", MediaTypeKind::Layer),
+    ("application/vnd.docker.image.rootfs.diff.tar.gzip", MediaTypeKind::Layer),
+    ("application/vnd.docker.image.rootfs.foreign.diff.tar.gzip", MediaTypeKind::Layer),
+    // Helm
+    ("application/vnd.cncf.helm.chart.content.v1.tar+gzip", MediaTypeKind::Layer),
+    ("application/vnd.cncf.helm.chart.provenance.v1.prov", MediaTypeKind::Layer),
+    // WASM
+    ("application/wasm", MediaTypeKind::Layer),
+    ("application/vnd.wasm.content.layer.v1+wasm", MediaTypeKind::Layer),
+    ("application/vnd.module.wasm.content.layer.v1+wasm", MediaTypeKind::Layer),
+    // SBOM and attestations
+    ("application/spdx+json", MediaTypeKind::Layer),
+    ("text/spdx", MediaTypeKind::Layer),
+    ("application/vnd.cyclonedx+json", MediaTypeKind::Layer),
+    ("application/vnd.cyclonedx+xml", MediaTypeKind::Layer),
+    ("application/vnd.in-toto+json", MediaTypeKind::Layer),
+    ("application/vnd.dsse.envelope.v1+json", MediaTypeKind::Layer),
+    // Signatures
+    ("application/vnd.cncf.notary.signature", MediaTypeKind::Layer),
+    ("application/jose+json", MediaTypeKind::Layer),
+    ("application/cose", MediaTypeKind::Layer),
+    ("application/vnd.dev.cosign.artifact.sig.v1+json", MediaTypeKind::Layer),
+    ("application/vnd.dev.sigstore.bundle+json", MediaTypeKind::Layer),
+    // Generic content
+    ("application/gzip", MediaTypeKind::Layer),
+    ("application/octet-stream", MediaTypeKind::Layer),
+    ("application/json", MediaTypeKind::Layer),
+    ("text/plain", MediaTypeKind::Layer),
+];
+
+/// Enumeration of the kinds of content a media type can describe,
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MediaTypeKind {
+    /// Image index or manifest list, served from the manifests api,
+    ///
+    Index,
+    /// Image or artifact manifest, served from the manifests api,
+    ///
+    Manifest,
+    /// Config blob, served from the blobs api,
+    ///
+    Config,
+    /// Layer blob, served from the blobs api,
+    ///
+    Layer,
+    /// Media type that is not registered, treated as a blob,
+    ///
+    Unknown,
+}
+
+impl MediaTypeKind {
+    /// Returns true if the content is served from the manifests api,
+    ///
+    pub fn is_manifest(&self) -> bool {
+        matches!(self, MediaTypeKind::Index | MediaTypeKind::Manifest)
+    }
+
+    /// Returns the registry api resource for this kind of content, either `manifests` or `blobs`,
+    ///
+    pub fn resource(&self) -> &'static str {
+        if self.is_manifest() {
+            "manifests"
+        } else {
+            "blobs"
+        }
+    }
+}
+
+/// Registry of media types and the kind of content they describe,
+///
+/// Downstream crates can register their own media types, Ex.
+///
+/// ```
+/// use lifec_registry::{MediaTypeKind, MediaTypeRegistry};
+///
+/// MediaTypeRegistry::register("application/vnd.example.manifest.v1+json", MediaTypeKind::Manifest);
+/// assert_eq!(MediaTypeKind::Manifest, MediaTypeRegistry::kind("application/vnd.example.manifest.v1+json"));
+/// ```
+///
+pub struct MediaTypeRegistry;
+
+impl MediaTypeRegistry {
+    /// Registers a media type, replacing the kind of a media type that is already known,
+    ///
+    pub fn register(media_type: impl AsRef<str>, kind: MediaTypeKind) {
+        let media_type = normalize(media_type.as_ref());
+
+        if let Ok(mut registered) = REGISTERED.write() {
+            registered.insert(media_type, kind);
+        }
+    }
+
+    /// Returns the kind of content a media type describes,
+    ///
+    /// Parameters are ignored, Ex. `application/json; charset=utf-8` is the same as `application/json`.
+    ///
+    pub fn kind(media_type: impl AsRef<str>) -> MediaTypeKind {
+        let media_type = normalize(media_type.as_ref());
+
+        let registered = REGISTERED
+            .read()
+            .ok()
+            .and_then(|registered| registered.get(&media_type).copied());
+
+        registered
+            .or_else(|| {
+                KNOWN_MEDIA_TYPES
+                    .iter()
+                    .find(|(known, _)| *known == media_type)
+                    .map(|(_, kind)| *kind)
+            })
+            .unwrap_or(MediaTypeKind::Unknown)
+    }
+
+    /// Returns true if the media type is registered,
+    ///
+    pub fn is_known(media_type: impl AsRef<str>) -> bool {
+        Self::kind(media_type) != MediaTypeKind::Unknown
+    }
+}
+
+/// Returns the media type w/o parameters, in lowercase,
+///
+fn normalize(media_type: &str) -> String {
+    media_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase()
+}
+
+#[allow(unused_imports)]
+mod tests {
+    use super::{MediaTypeKind, MediaTypeRegistry};
+
+    #[test]
+    fn test_media_type_registry() {
+        let expected = [
+            ("application/vnd.oci.image.index.v1+json", MediaTypeKind::Index),
+            ("application/vnd.docker.distribution.manifest.list.v2+json", MediaTypeKind::Index),
+            ("application/vnd.oci.image.manifest.v1+json", MediaTypeKind::Manifest),
+            ("application/vnd.oci.artifact.manifest.v1+json", MediaTypeKind::Manifest),
+            ("application/vnd.cncf.helm.config.v1+json", MediaTypeKind::Config),
+            ("application/vnd.cncf.helm.chart.content.v1.tar+gzip", MediaTypeKind::Layer),
+            ("application/vnd.wasm.content.layer.v1+wasm", MediaTypeKind::Layer),
+            ("application/spdx+json", MediaTypeKind::Layer),
+            ("application/vnd.cncf.notary.signature", MediaTypeKind::Layer),
+            ("application/vnd.dev.cosign.simplesigning.v1+json", MediaTypeKind::Config),
+            ("Application/JSON; charset=utf-8", MediaTypeKind::Layer),
+            ("application/vnd.unknown.v1", MediaTypeKind::Unknown),
+        ];
+        for (media_type, kind) in expected {
+            assert_eq!(kind, MediaTypeRegistry::kind(media_type), "{media_type}");
+        }
+
+        assert_eq!("manifests", MediaTypeKind::Index.resource());
+        assert_eq!("manifests", MediaTypeKind::Manifest.resource());
+        assert_eq!("blobs", MediaTypeKind::Config.resource());
+        assert_eq!("blobs", MediaTypeKind::Unknown.resource());
+
+        // Downstream crates can register their own media types
+        let custom = "application/vnd.test-registry.manifest.v1+json";
+        assert!(!MediaTypeRegistry::is_known(custom));
+        MediaTypeRegistry::register(custom, MediaTypeKind::Manifest);
+        assert_eq!(MediaTypeKind::Manifest, MediaTypeRegistry::kind(custom));
+        assert_eq!(MediaTypeKind::Manifest, MediaTypeRegistry::kind(format!("{custom}; charset=utf-8")));
+    }
+}
@@ -0,0 +1,265 @@
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hyper::body::HttpBody;
+use hyper::client::HttpConnector;
+use hyper::{Body, Client, Method, Request, StatusCode};
+use tracing::{debug, info, warn};
+
+use crate::Error;
+
+use super::{BlobStore, Hasher, Local};
+
+/// Header peers use to authenticate w/ each other,
+///
+pub const PEER_TOKEN_HEADER: &'static str = "x-ms-mirror-peer-token";
+
+/// Amount of time to wait for a peer to respond to a HEAD request,
+///
+pub const PEER_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Amount of time to wait for the next chunk of a blob from a peer, before falling back to the upstream,
+///
+pub const PEER_READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Amount of time a peer token is valid for, in either direction to allow for clock skew between nodes,
+///
+pub const PEER_TOKEN_MAX_AGE: Duration = Duration::from_secs(60);
+
+/// Mirrors on other nodes that blobs can be shared with,
+///
+/// On a local cache miss, peers are asked for the blob before the upstream. Peers serve their own local content on an
+/// internal route, authenticated w/ a short-lived token derived from a shared secret, a timestamp, and the digest being
+/// requested. Content from a peer is always verified against its digest before it is stored, and a peer that stops
+/// responding is given up on so that the blob is fetched from the upstream instead.
+///
+#[derive(Debug, Clone)]
+pub struct Peers {
+    /// Base addresses of peers, Ex. http://10.0.0.2:8578
+    ///
+    peers: Vec<String>,
+    /// Secret shared by all peers, if None serving to peers is disabled,
+    ///
+    secret: Option<String>,
+    /// Client for requests to peers,
+    ///
+    client: Client<HttpConnector>,
+}
+
+impl Peers {
+    /// Returns a new set of peers,
+    ///
+    pub fn new(peers: impl IntoIterator<Item = impl AsRef<str>>, secret: Option<String>) -> Self {
+        let peers = peers
+            .into_iter()
+            .map(|p| p.as_ref().trim().trim_end_matches('/').to_string())
+            .filter(|p| !p.is_empty())
+            .map(|p| {
+                if p.starts_with("http://") || p.starts_with("https://") {
+                    p
+                } else {
+                    format!("http://{p}")
+                }
+            })
+            .collect();
+
+        let mut connector = HttpConnector::new();
+        connector.set_connect_timeout(Some(PEER_TIMEOUT));
+
+        Self {
+            peers,
+            secret,
+            client: Client::builder().build(connector),
+        }
+    }
+
+    /// Reads a peer file, w/ a peer address per line, lines starting w/ `#` are skipped,
+    ///
+    pub fn read_peer_file(path: impl AsRef<Path>) -> Result<Vec<String>, Error> {
+        Ok(std::fs::read_to_string(path)?
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(str::to_string)
+            .collect())
+    }
+
+    /// Returns true if there are no peers configured,
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty()
+    }
+
+    /// Returns a token for a digest that is valid for `PEER_TOKEN_MAX_AGE`, returns None if there is no shared secret,
+    ///
+    pub fn token(&self, digest: impl AsRef<str>) -> Option<String> {
+        self.token_at(digest, unix_time())
+    }
+
+    /// Returns the token for a digest issued at a timestamp, in the format `<timestamp>.<signature>`,
+    ///
+    fn token_at(&self, digest: impl AsRef<str>, timestamp: u64) -> Option<String> {
+        self.secret.as_ref().map(|secret| {
+            let mut hasher = Hasher::default();
+            hasher.update(secret);
+            hasher.update(format!(":{timestamp}:"));
+            hasher.update(digest.as_ref());
+            format!("{timestamp}.{}", hasher.finish())
+        })
+    }
+
+    /// Returns true if the token is valid for the digest and has not expired,
+    ///
+    pub fn authorize(&self, digest: impl AsRef<str>, token: Option<&str>) -> bool {
+        let timestamp = match token
+            .and_then(|t| t.split_once('.'))
+            .and_then(|(timestamp, _)| timestamp.parse::<u64>().ok())
+        {
+            Some(timestamp) => timestamp,
+            None => return false,
+        };
+
+        if unix_time().abs_diff(timestamp) > PEER_TOKEN_MAX_AGE.as_secs() {
+            debug!("Peer token has expired");
+            return false;
+        }
+
+        match (self.token_at(digest, timestamp), token) {
+            (Some(expected), Some(token)) => constant_time_eq(expected.as_bytes(), token.as_bytes()),
+            _ => false,
+        }
+    }
+
+    /// Asks each peer for a blob, returns the local content of the first peer that returned verified content,
+    ///
+    /// The source is the repository the blob was requested from, and is recorded w/ the content, see `CacheIndex::source`.
+    ///
+    pub async fn fetch(&self, blob_store: &BlobStore, digest: impl AsRef<str>, source: impl AsRef<str>) -> Option<Local> {
+        let digest = digest.as_ref();
+
+        for peer in self.peers.iter() {
+            match self.fetch_from(peer, blob_store, digest, source.as_ref()).await {
+                Ok(Some(local)) => {
+                    info!("Fetched {digest} from peer {peer}");
+                    return Some(local);
+                }
+                Ok(None) => {
+                    debug!("Peer {peer} does not have {digest}");
+                }
+                Err(err) => {
+                    warn!("Could not fetch {digest} from peer {peer}, {err}");
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Fetches a blob from a peer and writes it to the blob store, returns None if the peer does not have the blob,
+    ///
+    async fn fetch_from(
+        &self,
+        peer: &str,
+        blob_store: &BlobStore,
+        digest: &str,
+        source: &str,
+    ) -> Result<Option<Local>, Error> {
+        let head = self.client.request(self.request(peer, Method::HEAD, digest)?);
+        match tokio::time::timeout(PEER_TIMEOUT, head).await {
+            Ok(response) => {
+                if response?.status() != StatusCode::OK {
+                    return Ok(None);
+                }
+            }
+            Err(_) => {
+                debug!("Peer {peer} timed out");
+                return Ok(None);
+            }
+        }
+
+        let get = self.client.request(self.request(peer, Method::GET, digest)?);
+        let response = match tokio::time::timeout(PEER_TIMEOUT, get).await {
+            Ok(response) => response?,
+            Err(_) => {
+                debug!("Peer {peer} timed out");
+                return Ok(None);
+            }
+        };
+        if response.status() != StatusCode::OK {
+            return Ok(None);
+        }
+
+        // If the peer stalls, the partial content is discarded when the writer is dropped
+        let mut writer = blob_store.writer(digest).await?.with_source(source);
+        let mut body = response.into_body();
+        loop {
+            match tokio::time::timeout(PEER_READ_TIMEOUT, body.data()).await {
+                Ok(Some(chunk)) => writer.write(chunk?).await?,
+                Ok(None) => break,
+                Err(_) => {
+                    warn!("Peer {peer} stopped sending {digest}");
+                    return Err(Error::external_dependency());
+                }
+            }
+        }
+
+        Ok(Some(writer.commit().await?))
+    }
+
+    /// Returns a request for a blob on a peer,
+    ///
+    fn request(&self, peer: &str, method: Method, digest: &str) -> Result<Request<Body>, Error> {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(format!("{peer}/peer/blobs/{digest}"));
+
+        if let Some(token) = self.token(digest) {
+            request = request.header(PEER_TOKEN_HEADER, token);
+        }
+
+        Ok(request.body(Body::empty())?)
+    }
+}
+
+/// Returns the current unix time in seconds,
+///
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Compares two byte slices w/o returning early, so that the time taken does not depend on where they differ,
+///
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+#[allow(unused_imports)]
+mod tests {
+    use super::{unix_time, Peers, PEER_TOKEN_MAX_AGE};
+
+    #[test]
+    fn test_peers() {
+        let digest = "sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+        let peers = Peers::new(["10.0.0.2:8578", " ", "http://10.0.0.3:8578/"], Some("secret".to_string()));
+        assert_eq!(vec!["http://10.0.0.2:8578", "http://10.0.0.3:8578"], peers.peers);
+
+        let token = peers.token(digest);
+        assert!(peers.authorize(digest, token.as_deref()));
+        assert!(!peers.authorize("sha256:other", token.as_deref()));
+        assert!(!peers.authorize(digest, None));
+
+        // Tokens expire, and cannot be reused w/ a different timestamp
+        let expired = unix_time() - PEER_TOKEN_MAX_AGE.as_secs() - 1;
+        assert!(!peers.authorize(digest, peers.token_at(digest, expired).as_deref()));
+        let signature = token.as_deref().and_then(|t| t.split_once('.')).map(|(_, s)| s).unwrap();
+        assert!(!peers.authorize(digest, Some(&format!("{}.{signature}", unix_time() + 1))));
+        assert!(!peers.authorize(digest, Some(signature)));
+
+        let peers = Peers::new(Vec::<String>::new(), None);
+        assert!(peers.is_empty());
+        assert!(!peers.authorize(digest, Some("anything")));
+    }
+}
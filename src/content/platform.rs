@@ -1,9 +1,12 @@
+use lifec::prelude::{AttributeIndex, ThunkContext};
 use serde::{Serialize, Deserialize};
 use specs::{Component, VecStorage};
 
+use super::Descriptor;
+
 /// Platform field of an image descriptor
-/// 
-#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+///
+#[derive(Component, Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[storage(VecStorage)]
 pub struct Platform {
     /// Architecture
@@ -13,4 +16,231 @@ pub struct Platform {
     /// Operating system variant
     #[serde(skip_serializing_if = "Option::is_none")]
     pub variant: Option<String>,
-}
\ No newline at end of file
+    /// Operating system version, Ex. 10.0.17763.1040 on windows
+    #[serde(rename = "os.version")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub os_version: Option<String>,
+    /// Operating system features, Ex. win32k on windows
+    #[serde(rename = "os.features")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub os_features: Option<Vec<String>>,
+}
+
+impl Platform {
+    /// Returns a platform w/ a normalized architecture and variant,
+    ///
+    pub fn new(os: impl Into<String>, architecture: impl AsRef<str>, variant: Option<&str>) -> Self {
+        let (architecture, default_variant) = normalize_architecture(architecture.as_ref());
+
+        Self {
+            architecture,
+            os: os.into().to_lowercase(),
+            variant: variant.map(normalize_variant).or(default_variant),
+            os_version: None,
+            os_features: None,
+        }
+    }
+
+    /// Returns the platform of the current host,
+    ///
+    pub fn host() -> Self {
+        Self::new(std::env::consts::OS, std::env::consts::ARCH, None)
+    }
+
+    /// Returns the target platform configured in the `platform` symbol, Ex. `: platform .symbol linux/arm/v7`,
+    ///
+    /// If the symbol is not set or cannot be parsed, the platform of the current host is returned.
+    ///
+    pub fn target(context: &ThunkContext) -> Self {
+        context
+            .search()
+            .find_symbol("platform")
+            .and_then(Self::parse)
+            .unwrap_or_else(Self::host)
+    }
+
+    /// Parses a platform, Ex. linux/arm64, linux/arm/v7, windows/amd64
+    ///
+    pub fn parse(platform: impl AsRef<str>) -> Option<Self> {
+        let mut parts = platform.as_ref().trim().split('/');
+
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(os), Some(arch), variant, None) if !os.is_empty() && !arch.is_empty() => {
+                Some(Self::new(os, arch, variant.filter(|v| !v.is_empty())))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns a copy of this platform w/ a normalized architecture and variant,
+    ///
+    pub fn normalized(&self) -> Self {
+        Self {
+            os_version: self.os_version.clone(),
+            os_features: self.os_features.clone(),
+            ..Self::new(&self.os, &self.architecture, self.variant.as_deref())
+        }
+    }
+
+    /// Returns a score for how well a candidate platform matches this platform, returns None if the candidate cannot run on this platform,
+    ///
+    /// The os and architecture must match. Then, an exact variant match scores highest, followed by an older variant that is
+    /// still compatible (Ex. arm/v6 on arm/v7), and candidates w/o a variant. Matching `os.version` and supporting every
+    /// `os.features` entry add to the score. Features are only checked if this platform lists the features it supports.
+    ///
+    pub fn score(&self, candidate: &Platform) -> Option<u32> {
+        let target = self.normalized();
+        let candidate = candidate.normalized();
+
+        if target.os != candidate.os || target.architecture != candidate.architecture {
+            return None;
+        }
+
+        let mut score = 1;
+
+        score += match (target.variant.as_deref(), candidate.variant.as_deref()) {
+            (Some(target), Some(candidate)) if target == candidate => 8,
+            (Some(target), Some(candidate)) => match (variant_level(target), variant_level(candidate)) {
+                (Some(target), Some(candidate)) if candidate < target => 4,
+                _ => return None,
+            },
+            (None, Some(_)) => 2,
+            (_, None) => 2,
+        };
+
+        match (target.os_version.as_deref(), candidate.os_version.as_deref()) {
+            (Some(target), Some(candidate)) if target == candidate => score += 4,
+            // Windows images are compatible w/ hosts on the same major.minor.build
+            (Some(target), Some(candidate)) if build(target) == build(candidate) => score += 2,
+            _ => {}
+        }
+
+        if let (Some(supported), Some(features)) = (target.os_features.as_ref(), candidate.os_features.as_ref()) {
+            if features.iter().all(|f| supported.contains(f)) {
+                score += 1;
+            } else {
+                return None;
+            }
+        }
+
+        Some(score)
+    }
+
+    /// Returns the descriptor w/ the best matching platform, descriptors w/o a platform are skipped,
+    ///
+    /// If more than one descriptor has the same score, the first one is returned.
+    ///
+    pub fn best_match<'a>(&self, descriptors: impl IntoIterator<Item = &'a Descriptor>) -> Option<&'a Descriptor> {
+        let mut best = None::<(u32, &'a Descriptor)>;
+
+        for descriptor in descriptors {
+            let score = match descriptor.platform.as_ref().and_then(|p| self.score(p)) {
+                Some(score) => score,
+                None => continue,
+            };
+
+            if best.map_or(true, |(best, _)| score > best) {
+                best = Some((score, descriptor));
+            }
+        }
+
+        best.map(|(_, descriptor)| descriptor)
+    }
+}
+
+/// Returns the normalized architecture, and the variant implied by the architecture name,
+///
+fn normalize_architecture(architecture: &str) -> (String, Option<String>) {
+    let architecture = architecture.to_lowercase();
+    let (architecture, variant) = match architecture.as_str() {
+        "x86_64" | "x86-64" | "amd64" => ("amd64", None),
+        "aarch64" | "arm64" => ("arm64", None),
+        "armhf" | "armv7" | "armv7l" => ("arm", Some("v7")),
+        "armel" | "armv6" | "armv6l" => ("arm", Some("v6")),
+        "armv5" | "armv5l" | "armv5tel" => ("arm", Some("v5")),
+        "i386" | "i686" | "x86" | "386" => ("386", None),
+        "powerpc64le" | "ppc64le" => ("ppc64le", None),
+        "powerpc64" | "ppc64" => ("ppc64", None),
+        "s390x" => ("s390x", None),
+        "riscv64" => ("riscv64", None),
+        arch => return (arch.to_string(), None),
+    };
+
+    (architecture.to_string(), variant.map(str::to_string))
+}
+
+/// Returns the normalized variant, Ex. 7 -> v7
+///
+fn normalize_variant(variant: &str) -> String {
+    let variant = variant.to_lowercase();
+    if variant.chars().all(|c| c.is_ascii_digit()) {
+        format!("v{variant}")
+    } else {
+        variant
+    }
+}
+
+/// Returns the level of an arm variant, Ex. v7 -> 7
+///
+fn variant_level(variant: &str) -> Option<u32> {
+    variant.strip_prefix('v').and_then(|v| v.split('.').next()).and_then(|v| v.parse().ok())
+}
+
+/// Returns the major.minor.build portion of an os version,
+///
+fn build(os_version: &str) -> String {
+    os_version.split('.').take(3).collect::<Vec<_>>().join(".")
+}
+
+#[allow(unused_imports)]
+mod tests {
+    use super::Platform;
+    use crate::{Descriptor, Digest};
+
+    #[test]
+    fn test_platform_matching() {
+        assert_eq!(Platform::parse("linux/arm/v7"), Some(Platform::new("linux", "armv7l", None)));
+        assert_eq!("arm64", Platform::new("linux", "aarch64", None).architecture);
+        assert_eq!("amd64", Platform::new("linux", "x86_64", None).architecture);
+        assert!(Platform::parse("linux").is_none());
+
+        let descriptor = |platform: &str| Descriptor {
+            digest: Digest::of(platform),
+            platform: Platform::parse(platform),
+            ..Default::default()
+        };
+        let index = vec![
+            descriptor("linux/amd64"),
+            descriptor("linux/arm/v6"),
+            descriptor("linux/arm/v7"),
+            descriptor("linux/arm64/v8"),
+        ];
+
+        let best = |target: &str| {
+            Platform::parse(target)
+                .and_then(|t| t.best_match(index.iter()).map(|d| d.digest.clone()))
+        };
+        assert_eq!(Some(Digest::of("linux/arm/v7")), best("linux/arm/v7"));
+        assert_eq!(Some(Digest::of("linux/arm/v6")), best("linux/arm/v6"));
+        assert_eq!(Some(Digest::of("linux/arm64/v8")), best("linux/aarch64"));
+        assert_eq!(None, best("linux/arm/v5"));
+        assert_eq!(None, best("windows/amd64"));
+
+        let mut windows = Platform::parse("windows/amd64").unwrap();
+        windows.os_version = Some("10.0.17763.1040".to_string());
+        let mut ltsc2019 = windows.clone();
+        ltsc2019.os_version = Some("10.0.17763.5000".to_string());
+        let mut ltsc2022 = windows.clone();
+        ltsc2022.os_version = Some("10.0.20348.100".to_string());
+        assert!(windows.score(&ltsc2019) > windows.score(&ltsc2022));
+
+        // Features are ignored unless the target lists the features it supports
+        let mut win32k = windows.clone();
+        win32k.os_features = Some(vec!["win32k".to_string()]);
+        assert!(windows.score(&win32k).is_some());
+        windows.os_features = Some(vec![]);
+        assert!(windows.score(&win32k).is_none());
+        windows.os_features = Some(vec!["win32k".to_string()]);
+        assert!(windows.score(&win32k) > windows.score(&ltsc2019));
+    }
+}
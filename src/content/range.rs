@@ -0,0 +1,272 @@
+use std::io::SeekFrom;
+use std::time::SystemTime;
+
+use hyper::body::{Bytes, Sender};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt};
+use tracing::error;
+
+use crate::Error;
+
+/// Maximum number of ranges a single request can ask for, requests w/ more ranges are served in full,
+///
+pub const MAX_RANGES: usize = 64;
+
+/// Size of chunks read from the source when writing a multipart body,
+///
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Inclusive range of bytes,
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    /// Offset of the first byte in the range,
+    ///
+    pub start: u64,
+    /// Offset of the last byte in the range,
+    ///
+    pub end: u64,
+}
+
+/// Result of evaluating a `Range` header against content of a known size,
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RangeRequest {
+    /// The full content should be served, either no range was requested or the header should be ignored,
+    ///
+    Full,
+    /// The satisfiable ranges that were requested,
+    ///
+    Partial(Vec<ByteRange>),
+    /// None of the requested ranges overlap the content,
+    ///
+    Unsatisfiable,
+}
+
+impl ByteRange {
+    /// Returns the number of bytes in this range,
+    ///
+    pub fn length(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    /// Returns the value of the `Content-Range` header for this range,
+    ///
+    pub fn content_range(&self, size: u64) -> String {
+        format!("bytes {}-{}/{size}", self.start, self.end)
+    }
+
+    /// Returns the part of a chunk that overlaps this range, where offset is the position of the chunk in the content,
+    ///
+    pub fn slice(&self, offset: u64, chunk: &Bytes) -> Option<Bytes> {
+        let last = (offset + chunk.len() as u64).checked_sub(1)?;
+        let (start, end) = (self.start.max(offset), self.end.min(last));
+
+        (start <= end).then(|| chunk.slice((start - offset) as usize..=(end - offset) as usize))
+    }
+}
+
+impl RangeRequest {
+    /// Parses the value of a `Range` header for content of the given size,
+    ///
+    /// Per RFC 9110, a header w/ an unknown unit or invalid syntax is ignored and the full content is served.
+    ///
+    pub fn parse(range: Option<&str>, size: u64) -> Self {
+        let specs = match range.map(str::trim).and_then(|r| r.strip_prefix("bytes=")) {
+            Some(specs) => specs,
+            None => return RangeRequest::Full,
+        };
+
+        let mut ranges = vec![];
+        let mut count = 0;
+        for spec in specs.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            count += 1;
+            if count > MAX_RANGES {
+                return RangeRequest::Full;
+            }
+
+            let (start, end) = match spec.split_once('-') {
+                Some(bounds) => bounds,
+                None => return RangeRequest::Full,
+            };
+
+            let last = size.saturating_sub(1);
+            let range = match (start.parse::<u64>(), end.parse::<u64>()) {
+                // bytes=<start>-<end>
+                (Ok(start), Ok(end)) if start <= end => (start < size).then_some(ByteRange {
+                    start,
+                    end: end.min(last),
+                }),
+                // bytes=<start>-
+                (Ok(start), Err(_)) if end.is_empty() => {
+                    (start < size).then_some(ByteRange { start, end: last })
+                }
+                // bytes=-<suffix-length>
+                (Err(_), Ok(suffix)) if start.is_empty() => (suffix > 0 && size > 0).then_some(ByteRange {
+                    start: size.saturating_sub(suffix),
+                    end: last,
+                }),
+                _ => return RangeRequest::Full,
+            };
+
+            ranges.extend(range);
+        }
+
+        if count == 0 {
+            RangeRequest::Full
+        } else if ranges.is_empty() {
+            RangeRequest::Unsatisfiable
+        } else {
+            RangeRequest::Partial(ranges)
+        }
+    }
+}
+
+/// Returns a new boundary for a `multipart/byteranges` body,
+///
+pub fn boundary() -> String {
+    let nanos = SystemTime::UNIX_EPOCH
+        .elapsed()
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+
+    format!("lifec_registry_{:x}{:x}", std::process::id(), nanos)
+}
+
+/// Returns the reader for a single range of a source,
+///
+pub async fn single<S>(mut source: S, range: ByteRange) -> Result<tokio::io::Take<S>, Error>
+where
+    S: AsyncRead + AsyncSeek + Unpin,
+{
+    source.seek(SeekFrom::Start(range.start)).await?;
+    Ok(source.take(range.length()))
+}
+
+/// Returns the content length and a streaming `multipart/byteranges` body for multiple ranges of a source,
+///
+/// The body is written by a background task, so only a single chunk of the source is held in memory at a time,
+///
+pub fn multipart<S>(
+    mut source: S,
+    size: u64,
+    ranges: Vec<ByteRange>,
+    content_type: impl AsRef<str>,
+    boundary: impl AsRef<str>,
+) -> (u64, hyper::Body)
+where
+    S: AsyncRead + AsyncSeek + Unpin + Send + 'static,
+{
+    let boundary = boundary.as_ref();
+    let parts = ranges
+        .into_iter()
+        .map(|range| {
+            let header = format!(
+                "\r\n--{boundary}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+                content_type.as_ref(),
+                range.content_range(size)
+            );
+            (header, range)
+        })
+        .collect::<Vec<_>>();
+    let closing = format!("\r\n--{boundary}--\r\n");
+
+    let length = parts
+        .iter()
+        .map(|(header, range)| header.len() as u64 + range.length())
+        .sum::<u64>()
+        + closing.len() as u64;
+
+    let (mut sender, body) = hyper::Body::channel();
+    tokio::spawn(async move {
+        for (header, range) in parts {
+            if sender.send_data(header.into()).await.is_err() {
+                return;
+            }
+
+            if let Err(err) = copy_range(&mut source, range, &mut sender).await {
+                error!("Could not write range {:?}, {err}", range);
+                sender.abort();
+                return;
+            }
+        }
+
+        sender.send_data(closing.into()).await.ok();
+    });
+
+    (length, body)
+}
+
+/// Copies a range of the source into the body sender,
+///
+async fn copy_range<S>(source: &mut S, range: ByteRange, sender: &mut Sender) -> Result<(), Error>
+where
+    S: AsyncRead + AsyncSeek + Unpin,
+{
+    source.seek(SeekFrom::Start(range.start)).await?;
+
+    let mut buffer = vec![0; CHUNK_SIZE];
+    let mut remaining = range.length();
+    while remaining > 0 {
+        let limit = remaining.min(CHUNK_SIZE as u64) as usize;
+        let read = source.read(&mut buffer[..limit]).await?;
+        if read == 0 {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+
+        sender.send_data(Bytes::copy_from_slice(&buffer[..read])).await?;
+        remaining -= read as u64;
+    }
+
+    Ok(())
+}
+
+#[allow(unused_imports)]
+mod tests {
+    use hyper::body::Bytes;
+
+    use super::{ByteRange, RangeRequest};
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(RangeRequest::Full, RangeRequest::parse(None, 100));
+        assert_eq!(RangeRequest::Full, RangeRequest::parse(Some("items=0-1"), 100));
+        assert_eq!(RangeRequest::Full, RangeRequest::parse(Some("bytes=5-1"), 100));
+        assert_eq!(RangeRequest::Full, RangeRequest::parse(Some("bytes=abc"), 100));
+
+        assert_eq!(
+            RangeRequest::Partial(vec![ByteRange { start: 0, end: 9 }]),
+            RangeRequest::parse(Some("bytes=0-9"), 100)
+        );
+        assert_eq!(
+            RangeRequest::Partial(vec![
+                ByteRange { start: 90, end: 99 },
+                ByteRange { start: 50, end: 99 },
+                ByteRange { start: 95, end: 99 },
+            ]),
+            RangeRequest::parse(Some("bytes=-10, 50-, 95-500"), 100)
+        );
+
+        // Ranges that do not overlap the content are skipped
+        assert_eq!(
+            RangeRequest::Partial(vec![ByteRange { start: 0, end: 0 }]),
+            RangeRequest::parse(Some("bytes=0-0,200-300"), 100)
+        );
+        assert_eq!(RangeRequest::Unsatisfiable, RangeRequest::parse(Some("bytes=100-"), 100));
+        assert_eq!(RangeRequest::Unsatisfiable, RangeRequest::parse(Some("bytes=-0"), 100));
+
+        assert_eq!("bytes 0-9/100", ByteRange { start: 0, end: 9 }.content_range(100));
+    }
+
+    #[test]
+    fn test_slice_range() {
+        let range = ByteRange { start: 3, end: 6 };
+        let chunk = Bytes::from_static(b"abcde");
+
+        assert_eq!(Some(Bytes::from_static(b"de")), range.slice(0, &chunk));
+        assert_eq!(Some(Bytes::from_static(b"fg")), range.slice(5, &Bytes::from_static(b"fghij")));
+        assert_eq!(Some(Bytes::from_static(b"bcde")), range.slice(2, &Bytes::from_static(b"abcde")));
+        assert_eq!(None, range.slice(10, &chunk));
+        assert_eq!(None, range.slice(0, &Bytes::from_static(b"abc")));
+        assert_eq!(None, range.slice(3, &Bytes::new()));
+    }
+}
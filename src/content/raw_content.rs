@@ -0,0 +1,118 @@
+use std::ops::Deref;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::Error;
+
+use super::Hasher;
+
+/// Parsed content that keeps the bytes it was parsed from,
+///
+/// The digest of a manifest is the digest of the exact bytes the registry returned, so re-serializing a parsed manifest
+/// produces content that no longer matches its digest. Content that is served or uploaded should always use `bytes()`.
+/// The parsed view is read-only, so that the two cannot drift apart.
+///
+#[derive(Debug, Clone)]
+pub struct RawContent<T> {
+    /// Original bytes of the content,
+    ///
+    bytes: Vec<u8>,
+    /// Parsed view of the bytes,
+    ///
+    parsed: T,
+}
+
+impl<T: DeserializeOwned> RawContent<T> {
+    /// Parses content, and keeps the original bytes,
+    ///
+    pub fn parse(bytes: impl Into<Vec<u8>>) -> Result<Self, Error> {
+        let bytes = bytes.into();
+        let parsed = serde_json::from_slice(&bytes)?;
+
+        Ok(Self { bytes, parsed })
+    }
+}
+
+impl<T: Serialize> RawContent<T> {
+    /// Serializes new content created by the mirror,
+    ///
+    /// Only use this for content that did not come from a registry, otherwise use `parse` to keep the original bytes.
+    ///
+    pub fn create(parsed: T) -> Result<Self, Error> {
+        let bytes = serde_json::to_vec(&parsed)?;
+
+        Ok(Self { bytes, parsed })
+    }
+}
+
+impl<T> RawContent<T> {
+    /// Returns the original bytes of the content,
+    ///
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Returns the parsed view of the content,
+    ///
+    pub fn parsed(&self) -> &T {
+        &self.parsed
+    }
+
+    /// Returns the sha256 digest of the original bytes,
+    ///
+    pub fn digest(&self) -> String {
+        let mut hasher = Hasher::default();
+        hasher.update(&self.bytes);
+        hasher.finish()
+    }
+
+    /// Returns the size of the original bytes,
+    ///
+    pub fn size(&self) -> u64 {
+        self.bytes.len() as u64
+    }
+
+    /// Consumes the content and returns the original bytes,
+    ///
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+impl<T> Deref for RawContent<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.parsed
+    }
+}
+
+#[allow(unused_imports)]
+mod tests {
+    use super::RawContent;
+    use crate::ImageManifest;
+
+    #[test]
+    fn test_raw_content() {
+        // Formatting and key order that serde would not reproduce
+        let manifest = br#"{
+  "schemaVersion": 2,
+  "layers": [],
+  "mediaType": "application/vnd.oci.image.manifest.v1+json",
+  "config": { "mediaType": "application/vnd.oci.image.config.v1+json", "digest": "sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a", "size": 2 }
+}"#;
+
+        let raw = RawContent::<ImageManifest>::parse(manifest.to_vec()).unwrap();
+        assert_eq!(&manifest[..], raw.bytes());
+        assert_eq!(2, raw.schema_version);
+        assert_eq!(manifest.len() as u64, raw.size());
+        assert_ne!(serde_json::to_vec(raw.parsed()).unwrap(), raw.bytes());
+
+        let created = RawContent::create(raw.parsed().clone()).unwrap();
+        assert_eq!(serde_json::to_vec(raw.parsed()).unwrap(), created.bytes());
+        assert_ne!(raw.digest(), created.digest());
+
+        assert!(RawContent::<ImageManifest>::parse(b"{".to_vec()).is_err());
+    }
+}
@@ -1,16 +1,25 @@
 use std::sync::Arc;
 
-use hyper::{Body, StatusCode, Uri};
+use hyper::body::{Bytes, HttpBody, Sender};
+use hyper::{Body, Method, StatusCode, Uri};
 use lifec::engine::NodeCommand;
 use lifec::prelude::{SpecialAttribute, ThunkContext};
 use lifec::state::AttributeIndex;
 use lifec_poem::RoutePlugin;
+use poem::http::HeaderValue;
 use poem::{Request, Response};
+use tokio::io::{AsyncRead, AsyncSeek};
 use tokio::sync::RwLock;
-use tracing::{debug, error, event, info, Level};
+use tracing::{debug, error, event, info, warn, Level};
 
 use crate::config::LoginConfig;
 use crate::hosts_config::MirrorHost;
+use crate::BlobStore;
+use crate::Error;
+use crate::ManifestCache;
+
+use super::range::{self, ByteRange, RangeRequest};
+use super::{BlobWriter, CachedManifest, FlightGuard, Hasher, Local};
 
 pub mod consts {
     /// While an image is being resolved, if the registry is capable of streaming the image then including this header will
@@ -28,6 +37,31 @@ pub mod consts {
     /// determine of the mirror should accept this request.
     ///
     pub const ACCEPT_IF_SUFFIX_HEADER: &'static str = "x-ms-accept-if-suffix";
+
+    /// Header registries include on the `/v2/` endpoint to advertise the version of the distribution api they implement.
+    ///
+    pub const API_VERSION_HEADER: &'static str = "docker-distribution-api-version";
+
+    /// Value of the api version header for the distribution api implemented by the mirror.
+    ///
+    pub const API_VERSION: &'static str = "registry/2.0";
+}
+
+/// Trait for the upstream api a proxied request is sent to,
+///
+pub trait UpstreamApi {
+    /// Returns the path of the upstream api relative to `/v2/`, Ex. `<repo>/manifests/<reference>`
+    ///
+    fn upstream_path(repo: &str, reference: &str) -> String;
+
+    /// Returns true if request bodies are streamed to the upstream by the mirror,
+    ///
+    /// When enabled, the operation should only authenticate w/ the upstream, Ex. `.login` and `.authn` w/o `.request`, and
+    /// the response of the operation is ignored. Otherwise, the body is cached on the context for the operation.
+    ///
+    fn streams_body() -> bool {
+        false
+    }
 }
 
 /// Pointer struct for fn implementations,
@@ -50,18 +84,15 @@ impl Registry {
         login_config: Arc<RwLock<LoginConfig>>,
     ) -> Response
     where
-        P: RoutePlugin + SpecialAttribute,
+        P: RoutePlugin + SpecialAttribute + UpstreamApi,
     {
         let mut repo = repo.into();
         let mut namespace = namespace.into();
 
-        if repo.starts_with("_tenant_") {
-            if let Some((tenant, _repo)) = repo.split_once("/") {
-                let tenant = tenant.trim_start_matches("_tenant_");
-                namespace = format!("{tenant}.{namespace}");
-                repo = _repo.to_string();
-                info!("Applied tenant workaround, namespace -> {namespace}, repo -> {repo}");
-            }
+        if let Some((tenant, _repo)) = Self::split_tenant(&repo).map(|(t, r)| (t.to_string(), r.to_string())) {
+            namespace = format!("{tenant}.{namespace}");
+            repo = _repo;
+            info!("Applied tenant workaround, namespace -> {namespace}, repo -> {repo}");
         }
 
         // Check if the request uri ends with the suffix value of the header, if not then return 503 Service Unavailable
@@ -142,6 +173,12 @@ impl Registry {
             match yielding.await {
                 Ok(mut context) => {
                     if let Some(body) = body {
+                        // Routes that stream request bodies send the request w/ the credentials the operation resolved, so
+                        // that the body is not buffered
+                        if P::streams_body() {
+                            return Self::forward_body(&context, request, body).await;
+                        }
+
                         context.cache_body(body);
                     }
 
@@ -159,11 +196,7 @@ impl Registry {
                             .and_then(|api| api.parse::<Uri>().ok())
                         {
                             event!(Level::DEBUG, "Handling redirect, {api}");
-                            let client = context.client().expect("should have client");
-                            match client.get(api).await {
-                                Ok(resp) => resp.into(),
-                                Err(err) => panic!("error following redirect {err}"),
-                            }
+                            Self::follow_redirect(&context, request, api).await
                         } else {
                             event!(Level::DEBUG, "No location header");
                             response.into()
@@ -185,6 +218,105 @@ impl Registry {
         }
     }
 
+    /// Returns the tenant and repository of a repo that uses the tenant workaround, Ex. `_tenant_<tenant>/<repo>`,
+    ///
+    /// Requests for these repos are proxied to the `<tenant>.<namespace>` upstream.
+    ///
+    pub fn split_tenant(repo: &str) -> Option<(&str, &str)> {
+        repo.strip_prefix("_tenant_")?.split_once('/')
+    }
+
+    /// Follows a redirect from the upstream, Ex. to blob storage,
+    ///
+    /// Headers that select the content being requested are copied to the redirected request, so that a range is served by
+    /// the redirect location instead of downloading the full content. Credentials are not copied, since the location is
+    /// usually a different host w/ its own authorization. If the redirect cannot be followed, a 502 is returned.
+    ///
+    async fn follow_redirect(context: &ThunkContext, request: &Request, location: Uri) -> Response {
+        let client = match context.client() {
+            Some(client) => client,
+            None => {
+                error!("Context is missing a client, cannot follow redirect");
+                return Self::bad_gateway();
+            }
+        };
+
+        let method = if request.method() == Method::HEAD {
+            Method::HEAD
+        } else {
+            Method::GET
+        };
+
+        let mut redirect = hyper::Request::builder().method(method).uri(location);
+        for name in ["range", "if-range", "accept"] {
+            if let Some(value) = request.headers().get(name) {
+                redirect = redirect.header(name, value);
+            }
+        }
+
+        let redirect = match redirect.body(Body::empty()) {
+            Ok(redirect) => redirect,
+            Err(err) => {
+                error!("Could not create redirect request, {err}");
+                return Self::bad_gateway();
+            }
+        };
+
+        match client.request(redirect).await {
+            Ok(response) => response.into(),
+            Err(err) => {
+                error!("Could not follow redirect, {err}");
+                Self::bad_gateway()
+            }
+        }
+    }
+
+    /// Sends a request w/ a body to the upstream api of a context, streaming the body,
+    ///
+    /// The context is expected to have been prepared by an operation that authenticated w/ the upstream, but did not send
+    /// the request itself, Ex. `.login` and `.authn` w/o `.request`.
+    ///
+    async fn forward_body(context: &ThunkContext, request: &Request, body: Body) -> Response {
+        let (api, client) = match (context.search().find_symbol("api"), context.client()) {
+            (Some(api), Some(client)) => (api, client),
+            _ => {
+                error!("Context is missing an api or client, cannot forward request body");
+                return Self::soft_fail();
+            }
+        };
+
+        let mut upstream = hyper::Request::builder()
+            .method(request.method().clone())
+            .uri(&api);
+        for (name, value) in request
+            .headers()
+            .iter()
+            .filter(|(n, _)| !matches!(n.as_str(), "host" | "user-agent" | "authorization"))
+        {
+            upstream = upstream.header(name, value);
+        }
+        if let Some(authorization) = context.search().find_symbol("Authorization") {
+            upstream = upstream.header("authorization", authorization);
+        }
+
+        let upstream = match upstream.body(body) {
+            Ok(upstream) => upstream,
+            Err(err) => {
+                error!("Could not create request for {api}, {err}");
+                return Self::soft_fail();
+            }
+        };
+
+        debug!("Forwarding {} {api}", request.method());
+        match client.request(upstream).await {
+            Ok(response) => response.into(),
+            Err(err) => {
+                error!("Could not forward request to {api}, {err}");
+                Self::soft_fail()
+            }
+        }
+    }
+
     /// Fails in a way that the runtime will fallback to the upstream server
     pub fn soft_fail() -> Response {
         Response::builder()
@@ -192,6 +324,374 @@ impl Registry {
             .finish()
     }
 
+    /// Returns a response for an upstream that could not be reached, or returned content that could not be used,
+    ///
+    pub fn bad_gateway() -> Response {
+        Response::builder()
+            .status(StatusCode::BAD_GATEWAY)
+            .finish()
+    }
+
+    /// Returns an error response in the format of the distribution spec, Ex. `{"errors":[{"code":"DIGEST_INVALID",..}]}`
+    ///
+    pub fn error_response(status: StatusCode, code: &str, message: impl AsRef<str>) -> Response {
+        let body = serde_json::json!({
+            "errors": [
+                {
+                    "code": code,
+                    "message": message.as_ref(),
+                }
+            ]
+        });
+
+        Response::builder()
+            .status(status)
+            .content_type("application/json")
+            .body(body.to_string())
+    }
+
+    /// Returns a response that serves content from the local blob store,
+    ///
+    /// If a range is passed w/ a GET request, only the requested ranges of the content are served,
+    ///
+    pub async fn serve_local(&self, method: &Method, local: Local, range: Option<&str>) -> Response {
+        if method == Method::HEAD {
+            return Response::builder()
+                .status(StatusCode::OK)
+                .header("docker-content-digest", &local.digest)
+                .header("content-length", local.size)
+                .header("accept-ranges", "bytes")
+                .content_type("application/octet-stream")
+                .finish();
+        }
+
+        match tokio::fs::File::open(&local.path).await {
+            Ok(file) => self.serve_content(file, &local.digest, local.size, range).await,
+            Err(err) => {
+                error!("Could not open local content {:?}, {err}", local.path);
+                Self::soft_fail()
+            }
+        }
+    }
+
+    /// Fills the local blob store w/ the body of an upstream response, returns a response w/ the same content,
+    ///
+    /// The upstream body is streamed to the client while it is written to the store, and is hashed on the fly. The last
+    /// chunk is held back until the content has been verified, so that the transfer can be aborted on a digest mismatch.
+    /// If the content cannot be written locally, Ex. the disk is full, the client continues to be served w/o storing it.
+    /// If a flight guard is passed, it is held until the content has been stored.
+    ///
+    /// If a single range was requested, but the upstream returned the full content, the requested range is served from the
+    /// upstream body as soon as it is received, while the rest of the content continues to be stored. Since the range is
+    /// sent before the content can be verified, a mismatch only prevents the content from being stored. If the size of the
+    /// content is unknown, or several ranges were requested, the full content is served.
+    ///
+    pub async fn store_local(
+        &self,
+        blob_store: &BlobStore,
+        source: impl AsRef<str>,
+        digest: impl AsRef<str>,
+        range: Option<&str>,
+        response: Response,
+        flight: Option<FlightGuard>,
+    ) -> Response {
+        let media_type = response.content_type().map(str::to_string);
+        let writer = match blob_store.writer(digest.as_ref()).await {
+            Ok(writer) => writer.with_media_type(media_type).with_source(source.as_ref()),
+            Err(err) => {
+                warn!("Could not store {} locally, {err}", digest.as_ref());
+                return response;
+            }
+        };
+
+        let (mut parts, body) = response.into_parts();
+        let body = Body::from(body);
+
+        let size = parts
+            .headers
+            .get("content-length")
+            .and_then(|l| l.to_str().ok())
+            .and_then(|l| l.parse::<u64>().ok());
+        let window = match size.map(|size| (size, RangeRequest::parse(range, size))) {
+            Some((size, RangeRequest::Partial(ranges))) if ranges.len() == 1 => {
+                let window = ranges[0];
+                parts.status = StatusCode::PARTIAL_CONTENT;
+                parts.headers.insert("content-length", HeaderValue::from(window.length()));
+                if let Ok(content_range) = HeaderValue::from_str(&window.content_range(size)) {
+                    parts.headers.insert("content-range", content_range);
+                }
+                Some(window)
+            }
+            _ => None,
+        };
+
+        let (sender, streaming) = Body::channel();
+        let digest = digest.as_ref().to_string();
+        tokio::spawn(async move {
+            let _flight = flight;
+            let mut sender = Some(sender);
+
+            if let Err(err) = Self::tee(body, writer, &mut sender, window).await {
+                error!("Could not stream {digest}, aborting transfer, {err}");
+                if let Some(sender) = sender {
+                    sender.abort();
+                }
+            }
+        });
+
+        Response::from_parts(parts, streaming.into())
+    }
+
+    /// Writes an upstream body to the blob store while forwarding it to a sender, returns the local content once verified,
+    ///
+    /// If the client goes away, the content continues to be written to the store. If the content cannot be written, the
+    /// writer is dropped, which removes the partial file, and the body continues to be forwarded. Only an error reading
+    /// the upstream body, or content that does not match its digest, is returned as an error.
+    ///
+    /// If a window is passed, only the bytes in the window are forwarded, and the sender is dropped once the window has
+    /// been sent, so that the client does not wait for the rest of the content.
+    ///
+    async fn tee(
+        mut body: Body,
+        writer: BlobWriter,
+        sender: &mut Option<Sender>,
+        window: Option<ByteRange>,
+    ) -> Result<Option<Local>, Error> {
+        let mut writer = Some(writer);
+        let mut pending: Option<Bytes> = None;
+        let mut offset = 0;
+
+        while let Some(chunk) = body.data().await {
+            let chunk = chunk?;
+
+            if let Some(w) = writer.as_mut() {
+                if let Err(err) = w.write(&chunk).await {
+                    warn!("Could not write content locally, continuing w/o storing it, {err}");
+                    writer.take();
+                }
+            }
+
+            let start = offset;
+            offset += chunk.len() as u64;
+
+            match window {
+                Some(window) => {
+                    if let Some(slice) = window.slice(start, &chunk) {
+                        Self::forward(sender, slice).await;
+                    }
+
+                    if offset > window.end {
+                        sender.take();
+                    }
+                }
+                None => {
+                    if let Some(previous) = pending.replace(chunk) {
+                        Self::forward(sender, previous).await;
+                    }
+                }
+            }
+        }
+
+        let local = match writer {
+            Some(writer) => match writer.commit().await {
+                Ok(local) => Some(local),
+                Err(err) if err.is_content_mismatch() => return Err(err),
+                Err(err) => {
+                    warn!("Could not store content locally, {err}");
+                    None
+                }
+            },
+            None => None,
+        };
+
+        if let Some(last) = pending {
+            Self::forward(sender, last).await;
+        }
+
+        Ok(local)
+    }
+
+    /// Forwards a chunk to a sender, if the receiver has been dropped the sender is removed,
+    ///
+    async fn forward(sender: &mut Option<Sender>, chunk: Bytes) {
+        if let Some(s) = sender.as_mut() {
+            if s.send_data(chunk).await.is_err() {
+                debug!("Client disconnected, continuing to store content locally");
+                sender.take();
+            }
+        }
+    }
+
+    /// Returns a response that serves content from a source, honoring the value of a `Range` header,
+    ///
+    /// A single range is served as `206 Partial Content` w/ a `Content-Range` header, multiple ranges are served as a
+    /// `multipart/byteranges` body, and if none of the ranges overlap the content a `416 Range Not Satisfiable` is returned.
+    ///
+    async fn serve_content<S>(
+        &self,
+        source: S,
+        digest: impl AsRef<str>,
+        size: u64,
+        range: Option<&str>,
+    ) -> Response
+    where
+        S: AsyncRead + AsyncSeek + Unpin + Send + 'static,
+    {
+        let response = Response::builder()
+            .header("docker-content-digest", digest.as_ref())
+            .header("accept-ranges", "bytes");
+
+        match RangeRequest::parse(range, size) {
+            RangeRequest::Full => response
+                .status(StatusCode::OK)
+                .header("content-length", size)
+                .content_type("application/octet-stream")
+                .body(poem::Body::from_async_read(source)),
+            RangeRequest::Unsatisfiable => response
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header("content-range", format!("bytes */{size}"))
+                .finish(),
+            RangeRequest::Partial(ranges) if ranges.len() == 1 => {
+                let range = ranges[0];
+                match range::single(source, range).await {
+                    Ok(source) => response
+                        .status(StatusCode::PARTIAL_CONTENT)
+                        .header("content-range", range.content_range(size))
+                        .header("content-length", range.length())
+                        .content_type("application/octet-stream")
+                        .body(poem::Body::from_async_read(source)),
+                    Err(err) => {
+                        error!("Could not read range {:?}, {err}", range);
+                        Self::soft_fail()
+                    }
+                }
+            }
+            RangeRequest::Partial(ranges) => {
+                let boundary = range::boundary();
+                let (length, body) =
+                    range::multipart(source, size, ranges, "application/octet-stream", &boundary);
+
+                response
+                    .status(StatusCode::PARTIAL_CONTENT)
+                    .header("content-length", length)
+                    .content_type(format!("multipart/byteranges; boundary={boundary}"))
+                    .body(body)
+            }
+        }
+    }
+
+    /// Returns a response that serves a manifest from the manifest cache,
+    ///
+    pub fn serve_manifest(&self, method: &Method, cached: CachedManifest) -> Response {
+        let mut response = Response::builder()
+            .status(StatusCode::OK)
+            .header("docker-content-digest", &cached.digest)
+            .header("content-length", cached.bytes.len())
+            .content_type(&cached.media_type);
+
+        if cached.stale {
+            response = response.header("warning", r#"110 - "Response is Stale""#);
+        }
+
+        if method == Method::HEAD {
+            response.finish()
+        } else {
+            response.body(cached.bytes)
+        }
+    }
+
+    /// Caches a manifest from an upstream response, returns a response w/ the same content,
+    ///
+    /// If the upstream returned a server error and stale-if-error is enabled, the last manifest the tag resolved to is
+    /// returned instead. The source the manifest was fetched from is recorded, see `CacheIndex::source`. If the manifest
+    /// was requested by digest and the content does not match it, a 502 w/ a `DIGEST_INVALID` error is returned.
+    ///
+    pub async fn cache_manifest(
+        &self,
+        manifest_cache: &ManifestCache,
+        source: impl AsRef<str>,
+        tag_key: Option<String>,
+        reference: impl AsRef<str>,
+        method: &Method,
+        accept: Option<&str>,
+        response: Response,
+    ) -> Response {
+        if response.status().is_server_error() && manifest_cache.stale_if_error() {
+            let cached = match tag_key.as_ref() {
+                Some(key) => manifest_cache.get_tag(key, true).await,
+                None => manifest_cache.get_digest(reference.as_ref()).await,
+            };
+
+            return match cached.filter(|c| c.accepted_by(accept)) {
+                Some(cached) => {
+                    warn!(
+                        "Upstream returned {}, serving {} from manifest cache",
+                        response.status(),
+                        reference.as_ref()
+                    );
+                    self.serve_manifest(method, cached)
+                }
+                None => response,
+            };
+        }
+
+        if !response.status().is_success() {
+            return response;
+        }
+
+        let digest = response.header("docker-content-digest").map(str::to_string);
+        let media_type = response.content_type().map(str::to_string);
+
+        if method == Method::HEAD {
+            if let (Some(key), Some(digest), Some(media_type)) = (tag_key, digest, media_type) {
+                if let Err(err) = manifest_cache.put_tag(key, digest, media_type).await {
+                    warn!("Could not cache tag, {err}");
+                }
+            }
+            return response;
+        }
+
+        let (parts, body) = response.into_parts();
+        match body.into_bytes().await {
+            Ok(bytes) => {
+                // A manifest requested by digest is never returned unless it matches the digest
+                if tag_key.is_none() && !Hasher::verify(reference.as_ref(), &bytes) {
+                    error!("Upstream returned content that does not match {}", reference.as_ref());
+                    return Self::error_response(
+                        StatusCode::BAD_GATEWAY,
+                        "DIGEST_INVALID",
+                        "upstream returned content that does not match the requested digest",
+                    );
+                }
+
+                let digest = tag_key
+                    .is_none()
+                    .then(|| reference.as_ref().to_string())
+                    .or(digest)
+                    .unwrap_or_else(|| {
+                        let mut hasher = Hasher::default();
+                        hasher.update(&bytes);
+                        hasher.finish()
+                    });
+
+                if let Some(media_type) = media_type {
+                    if let Err(err) = manifest_cache
+                        .put(tag_key, digest, media_type, &bytes, Some(source.as_ref()))
+                        .await
+                    {
+                        warn!("Could not cache manifest, {err}");
+                    }
+                }
+
+                Response::from_parts(parts, poem::Body::from_bytes(bytes))
+            }
+            Err(err) => {
+                error!("Could not read upstream response, {err}");
+                Self::soft_fail()
+            }
+        }
+    }
+
     /// Returns a context prepared with registry context,
     ///
     pub async fn prepare_registry_context<S>(
@@ -204,7 +704,7 @@ impl Registry {
         login_config: Arc<RwLock<LoginConfig>>,
     ) -> ThunkContext
     where
-        S: SpecialAttribute,
+        S: SpecialAttribute + UpstreamApi,
     {
         let mut context = context.clone();
         let workspace = context
@@ -218,10 +718,24 @@ impl Registry {
 
         let host = workspace.get_host().to_string();
         let repo = repo.into();
-        let resource = S::ident();
         let reference = reference.map(|r| r.into()).unwrap_or_default();
+        let path = S::upstream_path(&repo, &reference);
         let namespace = namespace.into();
 
+        // Query parameters other than the mirror's own `ns` parameter are forwarded, Ex. `n` and `last` for pagination
+        let query = request
+            .uri()
+            .query()
+            .map(|q| {
+                q.split('&')
+                    .filter(|p| !p.is_empty() && !p.starts_with("ns="))
+                    .collect::<Vec<_>>()
+                    .join("&")
+            })
+            .filter(|q| !q.is_empty())
+            .map(|q| format!("?{q}"))
+            .unwrap_or_default();
+
         info!(
             "Preparing proxy context - host: {}, namespace: {} repo: {}, reference: {}",
             &host, &namespace, &repo, &reference
@@ -243,7 +757,7 @@ impl Registry {
             )
             .with_symbol(
                 "api",
-                format!("https://{namespace}/v2/{repo}/{resource}/{reference}"),
+                format!("https://{namespace}/v2/{path}{query}"),
             );
 
         // If login credentials exist for namespace, then login
@@ -270,3 +784,153 @@ impl Registry {
         context.commit()
     }
 }
+
+#[allow(unused_imports)]
+mod tests {
+    use std::io::Cursor;
+
+    use hyper::body::{Bytes, HttpBody};
+    use hyper::{Body, StatusCode};
+    use poem::Response;
+
+    use super::Registry;
+    use crate::BlobStore;
+
+    #[tokio::test]
+    async fn test_store_local() {
+        let store = BlobStore::new(".test_store_local");
+        let registry = Registry::default();
+        let digest = "sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+        // The client receives the full body while it is stored
+        let (mut sender, body) = Body::channel();
+        let response = registry
+            .store_local(&store, "test.azurecr.io/redis", digest, None, Response::builder().body(body), None)
+            .await;
+        sender.send_data(Bytes::from("hel")).await.unwrap();
+        sender.send_data(Bytes::from("lo")).await.unwrap();
+        drop(sender);
+
+        let bytes = response.into_body().into_bytes().await.expect("should receive the body");
+        assert_eq!(b"hello".to_vec(), bytes.to_vec());
+
+        let local = store.get(digest).await.expect("should be stored");
+        assert_eq!(5, local.size);
+        assert!(store
+            .index()
+            .get(digest)
+            .map(|e| e.sources.contains("test.azurecr.io/redis"))
+            .unwrap_or_default());
+        store.remove(digest).await.unwrap();
+
+        // A body that does not match the digest is aborted before the last chunk is sent
+        let (mut sender, body) = Body::channel();
+        let response = registry
+            .store_local(&store, "test.azurecr.io/redis", digest, None, Response::builder().body(body), None)
+            .await;
+        sender.send_data(Bytes::from("not ")).await.unwrap();
+        sender.send_data(Bytes::from("hello")).await.unwrap();
+        drop(sender);
+
+        let mut body = Body::from(response.into_body());
+        let mut received = vec![];
+        let mut aborted = false;
+        while let Some(chunk) = body.data().await {
+            match chunk {
+                Ok(chunk) => received.extend_from_slice(&chunk),
+                Err(_) => {
+                    aborted = true;
+                    break;
+                }
+            }
+        }
+        assert!(aborted);
+        assert_eq!(b"not ".to_vec(), received);
+        assert!(store.get(digest).await.is_none());
+
+        // A range is served from the upstream body as it is received, and the full content is still stored
+        let (mut sender, body) = Body::channel();
+        let response = registry
+            .store_local(
+                &store,
+                "test.azurecr.io/redis",
+                digest,
+                Some("bytes=1-3"),
+                Response::builder().header("content-length", 5).body(body),
+                None,
+            )
+            .await;
+        assert_eq!(StatusCode::PARTIAL_CONTENT, response.status());
+        assert_eq!(Some("bytes 1-3/5"), response.header("content-range"));
+        assert_eq!(Some("3"), response.header("content-length"));
+
+        sender.send_data(Bytes::from("hel")).await.unwrap();
+        let mut body = Body::from(response.into_body());
+        assert_eq!(Some(Bytes::from("el")), body.data().await.transpose().unwrap());
+        sender.send_data(Bytes::from("lo")).await.unwrap();
+        assert_eq!(Some(Bytes::from("l")), body.data().await.transpose().unwrap());
+        assert!(body.data().await.is_none());
+        drop(sender);
+
+        let mut stored = false;
+        for _ in 0..100 {
+            if store.get(digest).await.is_some() {
+                stored = true;
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert!(stored);
+
+        std::fs::remove_dir_all(".test_store_local").unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_serve_content() {
+        let registry = Registry::default();
+        let content = b"hello world".to_vec();
+        let digest = "sha256:b94d27b9934d3e8a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9d";
+
+        let response = registry.serve_content(Cursor::new(content.clone()), digest, 11, None).await;
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(content, response.into_body().into_vec().await.unwrap());
+
+        let response = registry
+            .serve_content(Cursor::new(content.clone()), digest, 11, Some("bytes=6-"))
+            .await;
+        assert_eq!(StatusCode::PARTIAL_CONTENT, response.status());
+        assert_eq!(Some("bytes 6-10/11"), response.header("content-range"));
+        assert_eq!(Some("5"), response.header("content-length"));
+        assert_eq!(b"world".to_vec(), response.into_body().into_vec().await.unwrap());
+
+        let response = registry
+            .serve_content(Cursor::new(content.clone()), digest, 11, Some("bytes=0-4,-5"))
+            .await;
+        assert_eq!(StatusCode::PARTIAL_CONTENT, response.status());
+        let boundary = response
+            .content_type()
+            .and_then(|c| c.strip_prefix("multipart/byteranges; boundary="))
+            .expect("should have a boundary")
+            .to_string();
+        let length = response
+            .header("content-length")
+            .and_then(|l| l.parse::<usize>().ok())
+            .expect("should have a content length");
+        let body = String::from_utf8(response.into_body().into_vec().await.unwrap()).unwrap();
+        assert_eq!(length, body.len());
+        assert_eq!(
+            format!(
+                "\r\n--{boundary}\r\nContent-Type: application/octet-stream\r\nContent-Range: bytes 0-4/11\r\n\r\nhello\
+                 \r\n--{boundary}\r\nContent-Type: application/octet-stream\r\nContent-Range: bytes 6-10/11\r\n\r\nworld\
+                 \r\n--{boundary}--\r\n"
+            ),
+            body
+        );
+
+        let response = registry
+            .serve_content(Cursor::new(content), digest, 11, Some("bytes=20-"))
+            .await;
+        assert_eq!(StatusCode::RANGE_NOT_SATISFIABLE, response.status());
+        assert_eq!(Some("bytes */11"), response.header("content-range"));
+    }
+}
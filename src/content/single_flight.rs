@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::watch;
+
+/// Deduplicates concurrent fetches of the same content,
+///
+/// The first request for a key becomes the leader and fetches from the upstream. Requests that arrive while the leader
+/// is in flight become followers and wait for the leader to finish, after which they can serve the content from the
+/// local store.
+///
+#[derive(Debug, Clone, Default)]
+pub struct SingleFlight {
+    /// Map of keys to the receiver followers wait on,
+    ///
+    inflight: Arc<Mutex<HashMap<String, watch::Receiver<()>>>>,
+}
+
+/// Role of a request that joined a flight,
+///
+pub enum Flight {
+    /// This request should fetch the content, the flight ends when the guard is dropped,
+    ///
+    Leader(FlightGuard),
+    /// Another request is fetching the content,
+    ///
+    Follower(Follower),
+}
+
+/// Guard held by the leader of a flight,
+///
+pub struct FlightGuard {
+    /// Key of the flight,
+    ///
+    key: String,
+    /// Followers are released when the sender is dropped,
+    ///
+    _sender: watch::Sender<()>,
+    /// Map the flight is removed from when the guard is dropped,
+    ///
+    inflight: Arc<Mutex<HashMap<String, watch::Receiver<()>>>>,
+}
+
+/// Handle held by a follower of a flight,
+///
+pub struct Follower {
+    receiver: watch::Receiver<()>,
+}
+
+impl SingleFlight {
+    /// Returns the key for content in a namespace,
+    ///
+    pub fn key(namespace: impl AsRef<str>, digest: impl AsRef<str>) -> String {
+        format!("{}@{}", namespace.as_ref(), digest.as_ref())
+    }
+
+    /// Joins the flight for a key,
+    ///
+    pub fn join(&self, key: impl Into<String>) -> Flight {
+        let key = key.into();
+        let mut inflight = self.inflight.lock().expect("should be able to lock inflight map");
+
+        if let Some(receiver) = inflight.get(&key) {
+            return Flight::Follower(Follower {
+                receiver: receiver.clone(),
+            });
+        }
+
+        let (sender, receiver) = watch::channel(());
+        inflight.insert(key.clone(), receiver);
+
+        Flight::Leader(FlightGuard {
+            key,
+            _sender: sender,
+            inflight: self.inflight.clone(),
+        })
+    }
+
+    /// Waits for in-flight fetches of a key until `check` returns the content, or this request becomes the leader,
+    ///
+    /// If a leader finishes w/o storing the content, Ex. the upstream returned an error, the followers join the flight
+    /// again so that only one of them fetches the content next. A new leader checks for the content once more, in case it
+    /// was stored between the last check and joining the flight.
+    ///
+    pub async fn wait_or_lead<T, F, Fut>(&self, key: impl Into<String>, mut check: F) -> Result<T, FlightGuard>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Option<T>>,
+    {
+        let key = key.into();
+        loop {
+            match self.join(key.clone()) {
+                Flight::Leader(guard) => {
+                    return match check().await {
+                        Some(content) => Ok(content),
+                        None => Err(guard),
+                    };
+                }
+                Flight::Follower(follower) => {
+                    follower.wait().await;
+
+                    if let Some(content) = check().await {
+                        return Ok(content);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Follower {
+    /// Waits for the leader of the flight to finish,
+    ///
+    pub async fn wait(mut self) {
+        // The leader never sends a value, so this only returns after the sender is dropped
+        while self.receiver.changed().await.is_ok() {}
+    }
+}
+
+impl Drop for FlightGuard {
+    fn drop(&mut self) {
+        if let Ok(mut inflight) = self.inflight.lock() {
+            inflight.remove(&self.key);
+        }
+    }
+}
+
+#[allow(unused_imports)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::{Flight, SingleFlight};
+
+    #[tokio::test]
+    async fn test_single_flight() {
+        let single_flight = SingleFlight::default();
+        let key = SingleFlight::key("test.azurecr.io", "sha256:abc");
+
+        let leader = match single_flight.join(&key) {
+            Flight::Leader(leader) => leader,
+            Flight::Follower(_) => panic!("first request should lead"),
+        };
+
+        let follower = match single_flight.join(&key) {
+            Flight::Follower(follower) => follower,
+            Flight::Leader(_) => panic!("second request should follow"),
+        };
+
+        let waiting = tokio::spawn(follower.wait());
+        drop(leader);
+        waiting.await.expect("follower should be released");
+
+        assert!(matches!(single_flight.join(&key), Flight::Leader(_)));
+
+        // If the first leader fails, exactly one of the waiting requests fetches the content next
+        let leader = match single_flight.join(&key) {
+            Flight::Leader(leader) => leader,
+            Flight::Follower(_) => panic!("flight should have ended"),
+        };
+
+        let stored = Arc::new(AtomicBool::new(false));
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let mut waiting = vec![];
+        for _ in 0..4 {
+            let single_flight = single_flight.clone();
+            let key = key.clone();
+            let stored = stored.clone();
+            let fetches = fetches.clone();
+            waiting.push(tokio::spawn(async move {
+                let check = || {
+                    let stored = stored.clone();
+                    async move { stored.load(Ordering::SeqCst).then_some(()) }
+                };
+
+                if let Err(_guard) = single_flight.wait_or_lead(key, check).await {
+                    fetches.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                    stored.store(true, Ordering::SeqCst);
+                }
+            }));
+        }
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        drop(leader);
+        for waiting in waiting {
+            waiting.await.expect("request should finish");
+        }
+
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        assert!(stored.load(Ordering::SeqCst));
+    }
+}
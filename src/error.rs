@@ -78,6 +78,50 @@ impl Error {
         }
     }
 
+    /// Returns an error that indicates a digest could not be parsed, or uses an unsupported algorithm,
+    ///
+    pub fn invalid_digest(reason: &'static str) -> Self {
+        Error {
+            category: ErrorCategory::InvalidDigest(reason),
+        }
+    }
+
+    /// Returns an error that indicates an image reference could not be parsed,
+    ///
+    pub fn invalid_reference(reason: &'static str) -> Self {
+        Error {
+            category: ErrorCategory::InvalidReference(reason),
+        }
+    }
+
+    /// Returns an error that indicates content did not hash to the digest it was requested by,
+    ///
+    pub fn digest_mismatch(expected: impl Into<String>, actual: impl Into<String>) -> Self {
+        let (expected, actual) = (expected.into(), actual.into());
+        error!("Content digest mismatch, expected {expected}, actual {actual}");
+        Error {
+            category: ErrorCategory::DigestMismatch { expected, actual },
+        }
+    }
+
+    /// Returns an error that indicates content was not the size it was expected to be,
+    ///
+    pub fn size_mismatch(expected: u64, actual: u64) -> Self {
+        error!("Content size mismatch, expected {expected}, actual {actual}");
+        Error {
+            category: ErrorCategory::SizeMismatch { expected, actual },
+        }
+    }
+
+    /// Returns true if the category is a digest or size mismatch,
+    ///
+    pub fn is_content_mismatch(&self) -> bool {
+        match self.category {
+            ErrorCategory::DigestMismatch { .. } | ErrorCategory::SizeMismatch { .. } => true,
+            _ => false,
+        }
+    }
+
     /// Returns true if the category is recoverable,
     /// 
     pub fn is_recoverable(&self) -> bool {
@@ -124,6 +168,10 @@ pub enum ErrorCategory {
     CodeDefect,
     InvalidOperation(&'static str),
     RecoverableError(&'static str),
+    InvalidDigest(&'static str),
+    InvalidReference(&'static str),
+    DigestMismatch { expected: String, actual: String },
+    SizeMismatch { expected: u64, actual: u64 },
     Composite(Box<Self>, Box<Self>),
 }
 
@@ -228,6 +276,10 @@ impl From<Error> for lifec::error::Error {
             ErrorCategory::CodeDefect => lifec::error::Error::invalid_operation("code defect"),
             ErrorCategory::SystemEnvironment => lifec::error::Error::invalid_operation("system environment error"),
             ErrorCategory::InvalidOperation(reason) => lifec::error::Error::invalid_operation(reason),
+            ErrorCategory::InvalidDigest(reason) => lifec::error::Error::invalid_operation(reason),
+            ErrorCategory::InvalidReference(reason) => lifec::error::Error::invalid_operation(reason),
+            ErrorCategory::DigestMismatch { .. } => lifec::error::Error::invalid_operation("content digest mismatch"),
+            ErrorCategory::SizeMismatch { .. } => lifec::error::Error::invalid_operation("content size mismatch"),
             ErrorCategory::RecoverableError(message) if message.starts_with("skip") => lifec::error::Error::skip(message),
             ErrorCategory::RecoverableError(message) => lifec::error::Error::recoverable(message),
             ErrorCategory::Composite(a, b) => match (*a.clone(), *b.clone()) {
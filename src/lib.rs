@@ -6,12 +6,31 @@ mod content;
 pub use content::Platform;
 pub use content::ReferrersList;
 pub use content::Descriptor;
+pub use content::Digest;
 pub use content::ArtifactManifest;
 pub use content::ImageIndex;
 pub use content::ImageManifest;
+pub use content::RawContent;
+pub use content::MediaTypeKind;
+pub use content::MediaTypeRegistry;
 pub use content::Registry;
+pub use content::BlobStore;
+pub use content::ManifestCache;
+pub use content::CachedManifest;
+pub use content::SingleFlight;
+pub use content::Peers;
+pub use content::AccessCache;
 pub use content::consts;
 
+pub mod cache {
+    pub use crate::content::Eviction;
+    pub use crate::content::CacheEntry;
+    pub use crate::content::CacheKind;
+    pub use crate::content::EvictionSummary;
+    pub use crate::content::parse_size;
+    pub use crate::content::DEFAULT_GC_INTERVAL;
+}
+
 mod plugins;
 pub use plugins::Mirror;
 pub use plugins::Artifact;
@@ -20,6 +39,8 @@ pub use plugins::Login;
 pub use plugins::Discover;
 pub use plugins::Teleport;
 pub use plugins::Resolve;
+pub use plugins::ReferrersFallback;
+pub use plugins::PlatformResolver;
 
 cfg_editor! {
     pub use plugins::RemoteRegistry;
@@ -34,8 +55,12 @@ mod proxy;
 pub use proxy::RegistryProxy;
 pub use proxy::ProxyTarget;
 pub use proxy::Object;
+pub use proxy::ImageReference;
 pub use proxy::Manifests;
 pub use proxy::Blobs;
+pub use proxy::Tags;
+pub use proxy::Referrers;
+pub use proxy::Catalog;
 pub use proxy::OAuthToken;
 
 mod config;
@@ -56,4 +81,10 @@ pub use access_provider::AccessProvider;
 pub use access_provider::default_access_provider;
 
 mod error;
-pub use error::Error;
\ No newline at end of file
+pub use error::Error;
+pub use error::ErrorCategory;
+
+mod prefetch;
+pub use prefetch::Prefetch;
+pub use prefetch::PrefetchSummary;
+pub use prefetch::DEFAULT_PARALLELISM;
\ No newline at end of file
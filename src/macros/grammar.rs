@@ -0,0 +1,36 @@
+/// Regex for a repository name, from the distribution spec,
+///
+/// A name is one or more path components separated by `/`, each component is lowercase alphanumerics separated by a
+/// single `.`, a single `_`, a double `__`, or any number of `-`, Ex. library/redis, my.team/app, my-org/app__v2
+///
+macro_rules! name_regex {
+    () => {
+        r"[a-z0-9]+(?:(?:\.|_|__|-+)[a-z0-9]+)*(?:/[a-z0-9]+(?:(?:\.|_|__|-+)[a-z0-9]+)*)*"
+    };
+}
+
+/// Regex for a tag, from the distribution spec,
+///
+macro_rules! tag_regex {
+    () => {
+        r"[a-zA-Z0-9_][a-zA-Z0-9._-]{0,127}"
+    };
+}
+
+/// Regex for a digest, from the image spec, algorithms are validated when the digest is parsed,
+///
+macro_rules! digest_regex {
+    () => {
+        r"[a-z0-9]+(?:[+._-][a-z0-9]+)*:[a-zA-Z0-9=_-]+"
+    };
+}
+
+/// Returns a route path that matches a repository name followed by a resource, Ex. `repo_path!("manifests")`
+///
+/// The regex is anchored, since route regexes are matched against the rest of the request path.
+///
+macro_rules! repo_path {
+    ($resource:literal) => {
+        concat!("/:repo<^", name_regex!(), "/", $resource, ">")
+    };
+}
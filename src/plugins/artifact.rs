@@ -1,4 +1,4 @@
-use crate::{ArtifactManifest, ProxyTarget, consts::{OCI_ARTIFACTS_MANIFEST_MEDIA_TYPE, ORAS_ARTIFACTS_MANIFEST_MEDIA_TYPE}};
+use crate::{ArtifactManifest, Error, ImageReference, ProxyTarget, RawContent, consts::{OCI_ARTIFACTS_MANIFEST_MEDIA_TYPE, ORAS_ARTIFACTS_MANIFEST_MEDIA_TYPE}};
 use hyper::Method;
 use lifec::prelude::{
     AddDoc, AsyncContext, AttributeIndex, AttributeParser, BlockObject, BlockProperties,
@@ -11,6 +11,31 @@ use tracing::{event, Level};
 #[derive(Default)]
 pub struct Artifact;
 
+impl Artifact {
+    /// Returns the uri to resolve a subject or blob from,
+    ///
+    /// The value can be an image reference, Ex. myregistry.io/app:v1, or a uri. References w/o a registry are resolved
+    /// against the namespace of the plugin. Blob references must include the digest of the blob, Ex.
+    /// myregistry.io/app@sha256:<hex>, otherwise an error is returned.
+    ///
+    fn resolve_uri(tc: &ThunkContext, namespace: &str, value: &str, is_blob: bool) -> Result<String, Error> {
+        match ImageReference::parse_with_registry(value, namespace) {
+            Ok(reference) if is_blob && reference.digest().is_none() => {
+                Err(Error::invalid_reference("blob references must include a digest"))
+            }
+            Ok(reference) => {
+                let target = ProxyTarget::from((&reference, tc));
+                if is_blob {
+                    Ok(target.blob_url())
+                } else {
+                    Ok(target.manifest_with(reference.object().to_string()))
+                }
+            }
+            Err(_) => Ok(value.to_string()),
+        }
+    }
+}
+
 impl Plugin for Artifact {
     fn symbol() -> &'static str {
         "artifact"
@@ -31,13 +56,29 @@ impl Plugin for Artifact {
                         tc.search().find_symbol_values("blob"),
                     ) {
                         (Some(artifact_type), Some(subject), blob_vec) => {
+                            let subject = match Self::resolve_uri(&tc, proxy_target.namespace(), &subject, false) {
+                                Ok(subject) => subject,
+                                Err(err) => {
+                                    event!(Level::ERROR, "Could not resolve subject {subject}, {err}");
+                                    tc.copy_previous();
+                                    return Some(tc);
+                                }
+                            };
                             let subject_desc = proxy_target.resolve_descriptor(&subject).await;
                             let subject_desc = subject_desc.expect("should be a desc");
 
                             let mut blobs = vec![];
                             if let Some(blob) = blob_vec.first() {
                                 // TODO - handle list of blobs
-                                let blob_desc = proxy_target.resolve_descriptor(blob).await;
+                                let blob = match Self::resolve_uri(&tc, proxy_target.namespace(), blob, true) {
+                                    Ok(blob) => blob,
+                                    Err(err) => {
+                                        event!(Level::ERROR, "Could not resolve blob {blob}, {err}");
+                                        tc.copy_previous();
+                                        return Some(tc);
+                                    }
+                                };
+                                let blob_desc = proxy_target.resolve_descriptor(&blob).await;
                                 let blob_desc = blob_desc.expect("Should be a desc");
                                 blobs.push(blob_desc);
                             }
@@ -56,8 +97,10 @@ impl Plugin for Artifact {
 
                             event!(Level::DEBUG, "Artifact Manifest\n{:#?}", artifact_manifest);
 
-                            let body = serde_json::to_vec_pretty(&artifact_manifest)
-                                .expect("should be serializable");
+                            // The mirror is creating this content, so the manifest is serialized once here
+                            let body = RawContent::create(&artifact_manifest)
+                                .expect("should be serializable")
+                                .into_bytes();
 
                             let artifact_uri = format!("{}-link", proxy_target.manifest_url());
 
@@ -110,7 +153,7 @@ impl Plugin for Artifact {
                 }
             })
             .add_doc(docs, "The subject of this artifact")
-            .symbol("This should be an image reference uri to the subject. It will be resolved into a descriptor.");
+            .symbol("This should be an image reference to the subject, Ex. myregistry.io/app:v1, or a uri. It will be resolved into a descriptor.");
 
             docs.as_mut().add_custom_with("blob", |p, content| {
                 if let Some(last) = p.last_child_entity() {
@@ -119,7 +162,7 @@ impl Plugin for Artifact {
             })
             .add_doc(docs, "A blob of this artifact")
             .list()
-            .symbol("This should be an image reference uri to the blob. It will be resolved into a descriptor.");
+            .symbol("This should be an image reference w/ the digest of the blob, Ex. myregistry.io/app@sha256:<hex>, or a uri. It will be resolved into a descriptor.");
 
             docs.as_mut().add_custom_with("oci", |p, _| {
                 if let Some(last) = p.last_child_entity() {
@@ -1,7 +1,7 @@
 use crate::RegistryProxy;
 use lifec::prelude::{
-    AttributeIndex, AttributeParser, BlockObject, BlockProperties, Component, CustomAttribute,
-    HashMapStorage, Plugin, ThunkContext,
+    AddDoc, AttributeIndex, AttributeParser, BlockObject, BlockProperties, Component, CustomAttribute,
+    HashMapStorage, Plugin, ThunkContext, Value,
 };
 
 use lifec_poem::AppHost;
@@ -84,11 +84,29 @@ impl Plugin for Mirror {
     /// : .server   https://example.azurecr.io
     /// : .host     localhost:5000, pull, resolve, push
     /// : .https    hosts.crt
+    /// : .stale_if_error
+    /// : .manifest_ttl 300
     /// ```
     ///
     fn compile(parser: &mut AttributeParser) {
         if let Some(mut docs) = Self::start_docs(parser) {
+            let docs = &mut docs;
             docs.as_mut().with_custom::<RegistryProxy>();
+
+            docs.as_mut().add_custom_with("stale_if_error", |p, _| {
+                if let Some(last) = p.last_child_entity() {
+                    p.define_child(last, "stale_if_error", true);
+                }
+            })
+            .add_doc(docs, "Serves the last manifest a tag resolved to when the upstream returns a server error");
+
+            docs.as_mut().add_custom_with("manifest_ttl", |p, content| {
+                if let Some(last) = p.last_child_entity() {
+                    p.define_child(last, "manifest_ttl", Value::Symbol(content));
+                }
+            })
+            .add_doc(docs, "How long a cached tag is fresh before it is resolved w/ the upstream again")
+            .symbol("This should be the number of seconds, defaults to 300");
         }
     }
 }
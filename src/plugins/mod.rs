@@ -21,6 +21,13 @@ pub use login::Login;
 mod resolve;
 pub use resolve::Resolve;
 
+mod platform;
+pub use platform::PlatformResolver;
+
+mod referrers_fallback;
+pub use referrers_fallback::ReferrersFallback;
+pub use referrers_fallback::referrers_tag;
+
 cfg_editor! {
     mod remote_registry;
     pub use remote_registry::RemoteRegistry;
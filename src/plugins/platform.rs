@@ -0,0 +1,124 @@
+use hyper::{Body, Response};
+use lifec::prelude::{
+    AsyncContext, AttributeIndex, BlockObject, BlockProperties, CustomAttribute, Plugin,
+    ThunkContext,
+};
+use tracing::{debug, info, warn};
+
+use crate::Error;
+use crate::ImageIndex;
+use crate::MediaTypeKind;
+use crate::MediaTypeRegistry;
+use crate::Platform;
+use crate::ProxyTarget;
+
+/// Plugin that resolves an image index to the image manifest that best matches a target platform,
+///
+/// The target platform is read from the attribute value, Ex. `: .platform linux/arm/v7`. If the attribute does not have
+/// a value, the `platform` symbol from the config is used, and otherwise the platform of the current host.
+///
+#[derive(Default)]
+pub struct PlatformResolver;
+
+impl PlatformResolver {
+    /// Returns the image index from a cached response, and the response w/ its body restored,
+    ///
+    async fn parse_index(response: Response<Body>) -> Result<(Option<ImageIndex>, Response<Body>), Error> {
+        let is_index = response
+            .headers()
+            .get("content-type")
+            .and_then(|c| c.to_str().ok())
+            .map(|c| MediaTypeRegistry::kind(c) == MediaTypeKind::Index)
+            .unwrap_or_default();
+
+        if !is_index || !response.status().is_success() {
+            return Ok((None, response));
+        }
+
+        let (parts, body) = response.into_parts();
+        let bytes = hyper::body::to_bytes(body).await?;
+
+        let index = serde_json::from_slice::<ImageIndex>(&bytes)
+            .map_err(|err| debug!("Could not parse image index, {err}"))
+            .ok();
+
+        // Restore the original response, in case there is no match
+        Ok((index, Response::from_parts(parts, Body::from(bytes))))
+    }
+}
+
+impl Plugin for PlatformResolver {
+    fn symbol() -> &'static str {
+        "platform"
+    }
+
+    fn description() -> &'static str {
+        "Resolves an image index in the cached response to the image manifest that best matches the target platform"
+    }
+
+    fn caveats() -> &'static str {
+        "Responses that are not an image index, or indexes w/o a matching manifest, are passed through"
+    }
+
+    fn call(context: &mut ThunkContext) -> Option<AsyncContext> {
+        let response = context.take_response();
+
+        context.task_with_result(|_| {
+            let mut tc = context.clone();
+            async move {
+                let response = response.ok_or_else(|| {
+                    Error::recoverable_error("skip -- missing cached response, passing state through")
+                })?;
+
+                let platform = tc
+                    .state()
+                    .find_symbol("platform")
+                    .and_then(Platform::parse)
+                    .unwrap_or_else(|| Platform::target(&tc));
+
+                let (index, original) = Self::parse_index(response).await?;
+
+                match index.as_ref().and_then(|i| platform.best_match(i.manifests.iter())) {
+                    Some(descriptor) => {
+                        info!("Resolved {:?} to manifest {}", platform, descriptor.digest);
+                        tc.replace_symbol("digest", descriptor.digest.to_string());
+
+                        // The manifest is verified against the descriptor from the index before it is returned
+                        let target = ProxyTarget::try_from(&tc)?;
+                        let manifest = target.request_content(descriptor).await?;
+
+                        let response = Response::builder()
+                            .header("content-type", &descriptor.media_type)
+                            .header("docker-content-digest", descriptor.digest.as_str())
+                            .header("content-length", manifest.len())
+                            .body(Body::from(manifest))
+                            .map_err(Error::from)?;
+                        tc.cache_response(response);
+                    }
+                    None => {
+                        if index.is_some() {
+                            warn!("Image index does not have a manifest for {:?}", platform);
+                        }
+                        tc.cache_response(original);
+                    }
+                }
+
+                tc.copy_previous();
+                Ok(tc)
+            }
+        })
+    }
+}
+
+impl BlockObject for PlatformResolver {
+    fn query(&self) -> BlockProperties {
+        BlockProperties::default()
+            .require("REGISTRY_NAMESPACE")
+            .require("REGISTRY_REPO")
+            .optional("platform")
+    }
+
+    fn parser(&self) -> Option<CustomAttribute> {
+        Some(Self::as_custom_attr())
+    }
+}
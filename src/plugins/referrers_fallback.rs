@@ -0,0 +1,191 @@
+use hyper::{Body, Response, StatusCode};
+use lifec::prelude::{
+    AsyncContext, AttributeIndex, BlockObject, BlockProperties, CustomAttribute, Plugin,
+    ThunkContext,
+};
+use tracing::{debug, info};
+
+use crate::consts::OCI_IMAGE_INDEX;
+use crate::Error;
+use crate::ImageIndex;
+use crate::ProxyTarget;
+use crate::RawContent;
+
+/// Header a registry includes in a referrers response when the `artifactType` filter was applied,
+///
+pub const OCI_FILTERS_APPLIED_HEADER: &'static str = "oci-filters-applied";
+
+/// Plugin that lists referrers w/ the referrers tag schema, when the upstream does not support the referrers api,
+///
+/// ```markdown
+/// | ID     | Method         | API Endpoint                                                 | Success     | Failure           |
+/// | ------ | -------------- | ------------------------------------------------------------ | ----------- | ----------------- |
+/// | end-12 | `GET`          | `/v2/<name>/referrers/<digest>?artifactType=<artifactType>`  | `200`       | `404`/`400`       |
+/// ```
+///
+/// Registries that do not support the referrers api return a 404, in which case clients are expected to read the index
+/// tagged w/ `<alg>-<ref>` of the subject digest. This plugin does that on behalf of the client and returns the index as
+/// if the upstream had supported the referrers api.
+///
+#[derive(Default)]
+pub struct ReferrersFallback;
+
+impl ReferrersFallback {
+    /// Returns the referrers index from the referrers tag of a subject,
+    ///
+    async fn list_from_tag(target: &ProxyTarget, digest: &str) -> Result<RawContent<ImageIndex>, Error> {
+        let tag = referrers_tag(digest).ok_or_else(|| Error::invalid_operation("subject is not a digest"))?;
+
+        let request = target
+            .start_request()
+            .uri_str(target.manifest_with(&tag))
+            .header("accept", OCI_IMAGE_INDEX)
+            .finish();
+
+        match target.send_request(request).await {
+            Some(response) if response.status().is_success() => {
+                let bytes = hyper::body::to_bytes(response.into_body()).await?;
+                RawContent::parse(bytes.to_vec())
+            }
+            Some(response) if response.status() == StatusCode::NOT_FOUND => {
+                debug!("No referrers tag {tag}, returning an empty index");
+                RawContent::create(ImageIndex {
+                    schema_version: 2,
+                    media_type: OCI_IMAGE_INDEX.to_string(),
+                    ..Default::default()
+                })
+            }
+            Some(response) => Err(Error::external_dependency_with(response.status())),
+            None => Err(Error::external_dependency()),
+        }
+    }
+
+    /// Returns a referrers api response synthesized from the referrers tag of the requested subject,
+    ///
+    async fn fallback(tc: &ThunkContext) -> Result<Response<Body>, Error> {
+        let digest = tc
+            .search()
+            .find_symbol("REFERENCE")
+            .ok_or_else(|| Error::invalid_operation("missing subject digest"))?;
+        info!("Referrers api is not supported by upstream, using referrers tag for {digest}");
+
+        let target = ProxyTarget::try_from(tc)?;
+        let mut index = Self::list_from_tag(&target, &digest).await?;
+
+        // Filtering creates a new index, otherwise the referrers tag is served as-is
+        let artifact_type = tc.search().find_symbol("api").and_then(|api| artifact_type_filter(&api));
+        if let Some(artifact_type) = artifact_type.as_ref() {
+            let mut filtered = index.parsed().clone();
+            filtered
+                .manifests
+                .retain(|m| m.artifact_type.as_ref() == Some(artifact_type));
+            index = RawContent::create(filtered)?;
+        }
+
+        let mut response = Response::builder()
+            .status(StatusCode::OK)
+            .header("content-type", OCI_IMAGE_INDEX);
+        if artifact_type.is_some() {
+            response = response.header(OCI_FILTERS_APPLIED_HEADER, "artifactType");
+        }
+
+        Ok(response.body(Body::from(index.into_bytes()))?)
+    }
+}
+
+impl Plugin for ReferrersFallback {
+    fn symbol() -> &'static str {
+        "referrers_fallback"
+    }
+
+    fn description() -> &'static str {
+        "If the upstream does not support the referrers api, lists referrers w/ the referrers tag schema instead"
+    }
+
+    fn caveats() -> &'static str {
+        "Responses other than a 404 from the referrers api are passed through"
+    }
+
+    fn call(context: &mut ThunkContext) -> Option<AsyncContext> {
+        let response = context.take_response();
+
+        context.task_with_result(|_| {
+            let mut tc = context.clone();
+            async move {
+                match response {
+                    Some(response) if response.status() != StatusCode::NOT_FOUND => {
+                        tc.cache_response(response);
+                    }
+                    _ => {
+                        let response = Self::fallback(&tc).await?;
+                        tc.cache_response(response);
+                    }
+                }
+
+                tc.copy_previous();
+                Ok(tc)
+            }
+        })
+    }
+}
+
+impl BlockObject for ReferrersFallback {
+    fn query(&self) -> BlockProperties {
+        BlockProperties::default()
+            .require("REGISTRY_NAMESPACE")
+            .require("REGISTRY_REPO")
+            .require("REFERENCE")
+    }
+
+    fn parser(&self) -> Option<CustomAttribute> {
+        Some(Self::as_custom_attr())
+    }
+}
+
+/// Returns the referrers tag of a subject digest,
+///
+/// Per the distribution spec, the tag is `<alg>-<ref>`, w/ the algorithm truncated to 32 characters and the encoded
+/// digest truncated to 64 characters.
+///
+pub fn referrers_tag(digest: impl AsRef<str>) -> Option<String> {
+    let (alg, encoded) = digest.as_ref().split_once(':')?;
+    if alg.is_empty() || encoded.is_empty() {
+        return None;
+    }
+
+    let alg = &alg[..alg.len().min(32)];
+    let encoded = &encoded[..encoded.len().min(64)];
+    Some(format!("{alg}-{encoded}"))
+}
+
+/// Returns the value of the `artifactType` query parameter of a url,
+///
+fn artifact_type_filter(url: &str) -> Option<String> {
+    let (_, query) = url.split_once('?')?;
+    serde_urlencoded::from_str::<Vec<(String, String)>>(query)
+        .ok()?
+        .into_iter()
+        .find(|(name, _)| name == "artifactType")
+        .map(|(_, value)| value)
+        .filter(|value| !value.is_empty())
+}
+
+#[allow(unused_imports)]
+mod tests {
+    use super::{artifact_type_filter, referrers_tag};
+
+    #[test]
+    fn test_referrers_tag() {
+        assert_eq!(
+            Some("sha256-2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824".to_string()),
+            referrers_tag("sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824")
+        );
+        assert_eq!(None, referrers_tag("latest"));
+
+        assert_eq!(
+            Some("application/vnd.example+json".to_string()),
+            artifact_type_filter("https://test.azurecr.io/v2/hello/referrers/sha256:abc?artifactType=application%2Fvnd.example%2Bjson")
+        );
+        assert_eq!(None, artifact_type_filter("https://test.azurecr.io/v2/hello/referrers/sha256:abc"));
+    }
+}
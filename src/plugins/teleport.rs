@@ -5,13 +5,13 @@ use lifec::prelude::{
     AsyncContext, AttributeIndex, BlockObject, BlockProperties, CustomAttribute, Plugin,
     ThunkContext,
 };
-use logos::Logos;
 use tracing::info;
 use tracing::warn;
 
 use crate::Error;
 use crate::ImageIndex;
 use crate::Object;
+use crate::Platform;
 use crate::ProxyTarget;
 use crate::ReferrersList;
 
@@ -58,24 +58,13 @@ impl Plugin for Teleport {
 
                         let streamable = list.find_streamable_descriptors();
 
-                        let arch = if std::env::consts::ARCH == "x86_64" {
-                            "amd64"
-                        } else {
-                            std::env::consts::ARCH
-                        };
-                        let os = std::env::consts::OS;
+                        let platform = Platform::target(&tc);
 
-                        info!("Filtering streamable descriptors w/ os - {os}, arch - {arch}");
+                        info!("Filtering streamable descriptors w/ platform - {:?}", platform);
 
-                        let digest = if let Some(streamable_desc) = streamable.iter().find(|s| {
-                            if let Some(platform) = s.platform.as_ref() {
-                                platform.architecture == arch && platform.os == os
-                            } else {
-                                false
-                            }
-                        }) {
+                        let digest = if let Some(streamable_desc) = platform.best_match(streamable.iter()) {
                             info!("Streamable descriptor was found");
-                            streamable_desc.digest.clone()
+                            streamable_desc.digest.to_string()
                         } else {
                             warn!(
                                 "No streamable descriptor was not found, {:?} {:?}",
@@ -92,16 +81,12 @@ impl Plugin for Teleport {
                         let manifest_uri = ProxyTarget::try_from(&ptc)?;
 
                         let method = tc.search().find_symbol("REFERENCE").map(|r| {
-                            let object = Object::lexer(r.as_str()).next();
-                            match object {
-                                Some(obj) => match obj {
-                                    // We can teleport references, but not digests
-                                    crate::Object::Reference(_) => Method::HEAD,
-                                    // We can't teleport digests, so get the manifest
-                                    crate::Object::Digest(_) => Method::GET,
-                                    crate::Object::Error => Method::HEAD,
-                                },
-                                _ => Method::HEAD,
+                            match Object::parse(r.as_str()) {
+                                // We can teleport references, but not digests
+                                crate::Object::Reference(_) => Method::HEAD,
+                                // We can't teleport digests, so get the manifest
+                                crate::Object::Digest(_) => Method::GET,
+                                crate::Object::Error => Method::HEAD,
                             }
                         }).unwrap_or(Method::HEAD);
 
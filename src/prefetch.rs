@@ -0,0 +1,482 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use hyper::body::HttpBody;
+use hyper::client::HttpConnector;
+use hyper::{Body, Client, Request, StatusCode};
+use hyper_tls::HttpsConnector;
+use lifec::prelude::ThunkContext;
+use tokio::sync::Semaphore;
+use tracing::{debug, error, info};
+
+use crate::consts::{DOCKER_MANIFEST_LIST, DOCKER_V2_MANIFEST, OCI_IMAGE_INDEX, OCI_IMAGE_MANIFEST};
+use crate::content::{read_verified, Hasher};
+use crate::{
+    Descriptor, Error, ImageIndex, ImageManifest, ImageReference, MediaTypeKind, MediaTypeRegistry, Platform, ProxyTarget,
+};
+
+/// Default number of blobs to download at the same time,
+///
+pub const DEFAULT_PARALLELISM: usize = 4;
+
+/// Client used to pull through the mirror, the address can be either http or https,
+///
+type PrefetchClient = Client<HttpsConnector<HttpConnector>>;
+
+/// Warms the mirror's cache by pulling images through the mirror,
+///
+/// Each reference is resolved w/ the mirror's own manifest route, then the index is walked down to the image manifest
+/// that best matches the target platform, and the config and layers of each image are downloaded w/ the mirror's blob route. Since the
+/// mirror speaks the distribution api, the address can also be a plain registry.
+///
+#[derive(Debug, Clone)]
+pub struct Prefetch {
+    /// Address of the mirror, Ex. http://localhost:8578
+    ///
+    address: String,
+    /// Namespace to use for references that do not include a registry,
+    ///
+    default_namespace: Option<String>,
+    /// If true, images for all platforms in an index are downloaded,
+    ///
+    all_platforms: bool,
+    /// Platform to download images for, defaults to the platform of the current host,
+    ///
+    platform: Platform,
+    /// Maximum number of blobs to download at the same time,
+    ///
+    parallelism: usize,
+}
+
+/// Summary of a prefetch,
+///
+#[derive(Debug, Default)]
+pub struct PrefetchSummary {
+    /// Number of images that were resolved,
+    ///
+    pub images: usize,
+    /// Number of manifests downloaded,
+    ///
+    pub manifests: usize,
+    /// Number of blobs downloaded,
+    ///
+    pub blobs: usize,
+    /// Number of blob bytes downloaded,
+    ///
+    pub bytes: u64,
+    /// References or digests that could not be downloaded,
+    ///
+    pub failed: Vec<String>,
+}
+
+impl Prefetch {
+    /// Returns a new prefetch that pulls through the mirror at the given address,
+    ///
+    pub fn new(address: impl AsRef<str>) -> Self {
+        let address = address.as_ref().trim_end_matches('/');
+        let address = if address.starts_with("http://") || address.starts_with("https://") {
+            address.to_string()
+        } else {
+            format!("http://{address}")
+        };
+
+        Self {
+            address,
+            default_namespace: None,
+            all_platforms: false,
+            platform: Platform::host(),
+            parallelism: DEFAULT_PARALLELISM,
+        }
+    }
+
+    /// Sets the namespace used for references that do not include a registry,
+    ///
+    pub fn with_default_namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.default_namespace = Some(namespace.into());
+        self
+    }
+
+    /// Sets whether images for all platforms in an index are downloaded,
+    ///
+    pub fn with_all_platforms(mut self, all_platforms: bool) -> Self {
+        self.all_platforms = all_platforms;
+        self
+    }
+
+    /// Sets the platform to download images for,
+    ///
+    pub fn with_platform(mut self, platform: Platform) -> Self {
+        self.platform = platform;
+        self
+    }
+
+    /// Sets the maximum number of blobs to download at the same time,
+    ///
+    pub fn with_parallelism(mut self, parallelism: usize) -> Self {
+        self.parallelism = parallelism.max(1);
+        self
+    }
+
+    /// Prefetches a list of image references,
+    ///
+    pub async fn run(&self, references: impl IntoIterator<Item = impl AsRef<str>>) -> PrefetchSummary {
+        let client = Client::builder().build::<_, Body>(HttpsConnector::new());
+        let context = ThunkContext::default();
+        let semaphore = Arc::new(Semaphore::new(self.parallelism));
+        let mut summary = PrefetchSummary::default();
+
+        for image in references {
+            let image = image.as_ref();
+            let target = match self.parse(image) {
+                Ok(reference) => ProxyTarget::from((&reference, &context)),
+                Err(err) => {
+                    error!("Could not parse image reference {image}, {err}");
+                    summary.failed.push(image.to_string());
+                    continue;
+                }
+            };
+
+            let blobs = match self.resolve(&client, &target, &mut summary).await {
+                Ok(blobs) => blobs,
+                Err(err) => {
+                    error!("Could not resolve {image}, {err}");
+                    summary.failed.push(image.to_string());
+                    continue;
+                }
+            };
+            summary.images += 1;
+
+            let total = blobs.len();
+            let mut downloads = vec![];
+            for (index, (digest, size)) in blobs.into_iter().enumerate() {
+                let client = client.clone();
+                let semaphore = semaphore.clone();
+                let uri = self.uri(&target, "blobs", &digest);
+
+                downloads.push(tokio::spawn(async move {
+                    let _permit = semaphore.acquire_owned().await.expect("should not be closed");
+                    let result = download(&client, &uri, &digest, size).await;
+                    match &result {
+                        Ok(size) => info!("[{}/{total}] {digest}, {size} bytes", index + 1),
+                        Err(err) => error!("[{}/{total}] {digest}, {err}", index + 1),
+                    }
+                    (digest, result)
+                }));
+            }
+
+            for download in downloads {
+                match download.await {
+                    Ok((_, Ok(size))) => {
+                        summary.blobs += 1;
+                        summary.bytes += size;
+                    }
+                    Ok((digest, Err(_))) => summary.failed.push(digest),
+                    Err(err) => error!("Download task did not complete, {err}"),
+                }
+            }
+        }
+
+        summary
+    }
+
+    /// Resolves an image reference down to the digests and sizes of the blobs that should be downloaded,
+    ///
+    /// Nested indexes are skipped. If a platform's manifest cannot be downloaded, its digest is added to the failures of
+    /// the summary and the other platforms are still resolved.
+    ///
+    async fn resolve(
+        &self,
+        client: &PrefetchClient,
+        target: &ProxyTarget,
+        summary: &mut PrefetchSummary,
+    ) -> Result<BTreeMap<String, u64>, Error> {
+        let (media_type, body) = self.manifest(client, target, &target.object().to_string(), None).await?;
+        summary.manifests += 1;
+
+        let manifests = if media_type == OCI_IMAGE_INDEX || media_type == DOCKER_MANIFEST_LIST {
+            let index = serde_json::from_slice::<ImageIndex>(&body)?;
+            let candidates = index
+                .manifests
+                .iter()
+                .filter(|d| MediaTypeRegistry::kind(&d.media_type) != MediaTypeKind::Index);
+            let descriptors: Vec<&Descriptor> = if self.all_platforms {
+                candidates.collect()
+            } else {
+                self.platform.best_match(candidates).into_iter().collect()
+            };
+
+            let mut manifests = vec![];
+            for descriptor in descriptors {
+                let manifest = self
+                    .manifest(client, target, descriptor.digest.as_str(), Some(descriptor.size))
+                    .await
+                    .and_then(|(_, body)| Ok(serde_json::from_slice::<ImageManifest>(&body)?));
+
+                match manifest {
+                    Ok(manifest) => {
+                        summary.manifests += 1;
+                        manifests.push(manifest);
+                    }
+                    Err(err) => {
+                        error!("Could not download manifest {}, {err}", descriptor.digest);
+                        summary.failed.push(descriptor.digest.to_string());
+                    }
+                }
+            }
+
+            if manifests.is_empty() {
+                return Err(Error::invalid_operation("index does not have a manifest that could be downloaded for this platform"));
+            }
+            manifests
+        } else {
+            vec![serde_json::from_slice::<ImageManifest>(&body)?]
+        };
+
+        Ok(manifests
+            .iter()
+            .flat_map(|m| std::iter::once(&m.config).chain(m.layers.iter()))
+            .map(|d| (d.digest.to_string(), d.size))
+            .collect())
+    }
+
+    /// Downloads a manifest, returns the media type and content,
+    ///
+    /// Manifests requested by digest are verified against the digest and the expected size, if known. Manifests requested by
+    /// tag are verified against the digest returned by the mirror, if any.
+    ///
+    async fn manifest(
+        &self,
+        client: &PrefetchClient,
+        target: &ProxyTarget,
+        object: &str,
+        size: Option<u64>,
+    ) -> Result<(String, Vec<u8>), Error> {
+        let request = Request::get(self.uri(target, "manifests", object))
+            .header(
+                "accept",
+                [OCI_IMAGE_INDEX, DOCKER_MANIFEST_LIST, OCI_IMAGE_MANIFEST, DOCKER_V2_MANIFEST].join(", "),
+            )
+            .body(Body::empty())?;
+
+        let response = client.request(request).await?;
+        if response.status() != StatusCode::OK {
+            debug!("Manifest request returned {}", response.status());
+            return Err(Error::external_dependency_with(response.status()));
+        }
+
+        let media_type = response
+            .headers()
+            .get("content-type")
+            .and_then(|c| c.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+
+        let digest = if object.contains(':') {
+            Some(object.to_string())
+        } else {
+            response
+                .headers()
+                .get("docker-content-digest")
+                .and_then(|d| d.to_str().ok())
+                .map(str::to_string)
+        };
+
+        let body = match digest {
+            Some(digest) => read_verified(response.into_body(), digest, size).await?,
+            None => hyper::body::to_bytes(response.into_body()).await?.to_vec(),
+        };
+        Ok((media_type, body))
+    }
+
+    /// Returns the uri of a resource on the mirror,
+    ///
+    fn uri(&self, target: &ProxyTarget, resource: &str, object: &str) -> String {
+        format!(
+            "{}/v2/{}/{resource}/{object}?ns={}",
+            self.address,
+            target.repo(),
+            target.namespace()
+        )
+    }
+
+    /// Parses an image reference, Ex. example.azurecr.io/library/redis:7, library/redis@sha256:..
+    ///
+    /// References w/o a registry are resolved against the default namespace, or Docker Hub if there is none.
+    ///
+    fn parse(&self, image: &str) -> Result<ImageReference, Error> {
+        match self.default_namespace.as_ref() {
+            Some(namespace) => ImageReference::parse_with_registry(image, namespace),
+            None => ImageReference::parse(image),
+        }
+    }
+}
+
+/// Downloads a blob and verifies it against its digest and size, returns the number of bytes downloaded,
+///
+/// Reading stops as soon as the body is larger than the expected size, see `read_verified`.
+///
+async fn download(
+    client: &PrefetchClient,
+    uri: &str,
+    digest: &str,
+    size: u64,
+) -> Result<u64, Error> {
+    let mut hasher =
+        Hasher::for_digest(digest).ok_or_else(|| Error::invalid_operation("unsupported digest format"))?;
+
+    let response = client.get(uri.parse()?).await?;
+    if response.status() != StatusCode::OK {
+        debug!("Blob request returned {}", response.status());
+        return Err(Error::external_dependency_with(response.status()));
+    }
+
+    let mut body = response.into_body();
+    let mut downloaded = 0;
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        hasher.update(&chunk);
+        downloaded += chunk.len() as u64;
+
+        if downloaded > size {
+            return Err(Error::size_mismatch(size, downloaded));
+        }
+    }
+
+    if downloaded != size {
+        return Err(Error::size_mismatch(size, downloaded));
+    }
+
+    let actual = hasher.finish();
+    if actual != digest {
+        return Err(Error::digest_mismatch(digest, actual));
+    }
+
+    Ok(downloaded)
+}
+
+#[allow(unused_imports)]
+mod tests {
+    use std::convert::Infallible;
+
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Response, Server};
+
+    use super::Prefetch;
+    use crate::content::Hasher;
+    use crate::Platform;
+
+    fn digest(content: &str) -> String {
+        let mut hasher = Hasher::default();
+        hasher.update(content);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_parse_reference() {
+        let prefetch = Prefetch::new("localhost:8578").with_default_namespace("example.azurecr.io");
+        let digest = digest("hello");
+
+        assert_eq!(
+            "example.azurecr.io/library/redis",
+            prefetch.parse("library/redis").unwrap().to_string()
+        );
+        assert_eq!(
+            "localhost:5000/redis:7",
+            prefetch.parse("localhost:5000/redis:7").unwrap().to_string()
+        );
+        assert_eq!(
+            Some(digest.as_str()),
+            prefetch
+                .parse(&format!("test.io/redis@{digest}"))
+                .unwrap()
+                .digest()
+                .map(|d| d.as_str())
+        );
+        assert_eq!(
+            "docker.io/library/redis",
+            Prefetch::new("localhost:8578").parse("redis").unwrap().to_string()
+        );
+        assert!(prefetch.parse("test.io/redis@sha256:abc").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_prefetch() {
+        let platform = Platform::host();
+        let config = "{}";
+        let layer = "layer";
+        let manifest = format!(
+            r#"{{"schemaVersion":2,"mediaType":"application/vnd.oci.image.manifest.v1+json","config":{{"mediaType":"application/vnd.oci.image.config.v1+json","digest":"{}","size":2}},"layers":[{{"mediaType":"application/vnd.oci.image.layer.v1.tar","digest":"{}","size":5}}]}}"#,
+            digest(config),
+            digest(layer)
+        );
+        let index = format!(
+            r#"{{"schemaVersion":2,"mediaType":"application/vnd.oci.image.index.v1+json","manifests":[{{"mediaType":"application/vnd.oci.image.manifest.v1+json","digest":"{}","size":{},"platform":{{"os":"{}","architecture":"{}"}}}},{{"mediaType":"application/vnd.oci.image.manifest.v1+json","digest":"sha256:0000000000000000000000000000000000000000000000000000000000000000","size":2,"platform":{{"os":"plan9","architecture":"none"}}}},{{"mediaType":"application/vnd.oci.image.index.v1+json","digest":"sha256:1111111111111111111111111111111111111111111111111111111111111111","size":2,"platform":{{"os":"{}","architecture":"{}"}}}}]}}"#,
+            digest(&manifest),
+            manifest.len(),
+            platform.os,
+            platform.architecture,
+            platform.os,
+            platform.architecture
+        );
+
+        // Stand-in registry
+        let content = vec![
+            ("/v2/library/test/manifests/latest".to_string(), "application/vnd.oci.image.index.v1+json", index.clone()),
+            (format!("/v2/library/test/manifests/{}", digest(&manifest)), "application/vnd.oci.image.manifest.v1+json", manifest.clone()),
+            (format!("/v2/library/test/blobs/{}", digest(config)), "application/octet-stream", config.to_string()),
+            (format!("/v2/library/test/blobs/{}", digest(layer)), "application/octet-stream", layer.to_string()),
+            ("/v2/library/corrupt/manifests/latest".to_string(), "application/vnd.oci.image.index.v1+json", index),
+            (format!("/v2/library/corrupt/manifests/{}", digest(&manifest)), "application/vnd.oci.image.manifest.v1+json", manifest.replace("layer", "other")),
+        ];
+        let make_service = make_service_fn(move |_| {
+            let content = content.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: hyper::Request<Body>| {
+                    let response = match content.iter().find(|(path, ..)| path == request.uri().path()) {
+                        Some((_, media_type, body)) => Response::builder()
+                            .header("content-type", *media_type)
+                            .body(Body::from(body.clone())),
+                        None => Response::builder().status(404).body(Body::empty()),
+                    };
+                    async move { response }
+                }))
+            }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let address = server.local_addr();
+        tokio::spawn(server);
+
+        let summary = Prefetch::new(address.to_string())
+            .with_default_namespace("test.azurecr.io")
+            .run(["library/test"])
+            .await;
+
+        assert_eq!(1, summary.images);
+        assert_eq!(2, summary.manifests);
+        assert_eq!(2, summary.blobs);
+        assert_eq!(7, summary.bytes);
+        assert!(summary.failed.is_empty());
+
+        let summary = Prefetch::new(address.to_string())
+            .with_default_namespace("test.azurecr.io")
+            .with_all_platforms(true)
+            .run(["library/test"])
+            .await;
+
+        // Platforms that cannot be downloaded are reported, nested indexes are skipped, and the other platforms are still downloaded
+        assert_eq!(1, summary.images);
+        assert_eq!(2, summary.blobs);
+        assert_eq!(
+            vec!["sha256:0000000000000000000000000000000000000000000000000000000000000000".to_string()],
+            summary.failed
+        );
+
+        // Manifests that do not match their digest are not used
+        let summary = Prefetch::new(address.to_string())
+            .with_default_namespace("test.azurecr.io")
+            .run(["library/corrupt"])
+            .await;
+        assert_eq!(0, summary.images);
+        assert_eq!(vec![digest(&manifest), "library/corrupt".to_string()], summary.failed);
+    }
+}
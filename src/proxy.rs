@@ -1,5 +1,14 @@
 use crate::config::LoginConfig;
 use crate::default_access_provider;
+use crate::BlobStore;
+use crate::ManifestCache;
+use crate::SingleFlight;
+use crate::Peers;
+use crate::AccessCache;
+use crate::content::DEFAULT_ACCESS_CHECK_TTL;
+use crate::cache::parse_size;
+use crate::cache::Eviction;
+use crate::cache::DEFAULT_GC_INTERVAL;
 use crate::Artifact;
 use crate::ArtifactManifest;
 use crate::Authenticate;
@@ -9,7 +18,9 @@ use crate::ImageIndex;
 use crate::ImageManifest;
 use crate::Login;
 use crate::Mirror;
+use crate::PlatformResolver;
 use crate::Resolve;
+use crate::ReferrersFallback;
 use crate::Teleport;
 use lifec::prelude::AttributeParser;
 use lifec::prelude::Block;
@@ -36,11 +47,15 @@ use poem::Route;
 use specs::WorldExt;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
+use tracing::error;
 use tracing::info;
+use tracing::warn;
 
 mod proxy_target;
 pub use proxy_target::Object;
+pub use proxy_target::ImageReference;
 pub use proxy_target::ProxyTarget;
 
 mod manifests;
@@ -52,6 +67,17 @@ pub use blobs::Blobs;
 mod blobs_uploads;
 pub use blobs_uploads::BlobsUploads;
 
+mod tags;
+pub use tags::Tags;
+
+mod referrers;
+pub use referrers::Referrers;
+
+mod catalog;
+pub use catalog::Catalog;
+
+mod link;
+
 mod proxy_route;
 use proxy_route::AddRoute;
 pub use proxy_route::ProxyRoute;
@@ -66,6 +92,12 @@ use config::handle_config;
 mod login;
 use login::handle_login;
 
+mod peer;
+use peer::handle_peer_blob;
+
+mod api_version;
+use api_version::handle_api_version;
+
 /// Struct for creating a customizable registry proxy,
 ///
 /// This proxy is a server that intercepts registry requests intended for upstream registries,
@@ -89,6 +121,9 @@ impl SpecialAttribute for RegistryProxy {
         parser.with_custom::<ProxyRoute<Manifests>>();
         parser.with_custom::<ProxyRoute<Blobs>>();
         parser.with_custom::<ProxyRoute<BlobsUploads>>();
+        parser.with_custom::<ProxyRoute<Tags>>();
+        parser.with_custom::<ProxyRoute<Referrers>>();
+        parser.with_custom::<ProxyRoute<Catalog>>();
     }
 }
 
@@ -124,6 +159,8 @@ impl Project for RegistryProxy {
             runtime.install_with_custom::<Resolve>("");
             runtime.install_with_custom::<Discover>("");
             runtime.install_with_custom::<Artifact>("");
+            runtime.install_with_custom::<ReferrersFallback>("");
+            runtime.install_with_custom::<PlatformResolver>("");
 
             runtime
         }
@@ -140,6 +177,8 @@ impl Project for RegistryProxy {
             runtime.install_with_custom::<Resolve>("");
             runtime.install_with_custom::<Discover>("");
             runtime.install_with_custom::<Artifact>("");
+            runtime.install_with_custom::<ReferrersFallback>("");
+            runtime.install_with_custom::<PlatformResolver>("");
             runtime.install_with_custom::<AzureGuest>("");
             runtime.install_with_custom::<AzureAgent>("");
             runtime.install_with_custom::<AzureDispatcher>("");
@@ -159,6 +198,9 @@ impl Project for RegistryProxy {
         world.register::<ProxyRoute<Manifests>>();
         world.register::<ProxyRoute<Blobs>>();
         world.register::<ProxyRoute<BlobsUploads>>();
+        world.register::<ProxyRoute<Tags>>();
+        world.register::<ProxyRoute<Referrers>>();
+        world.register::<ProxyRoute<Catalog>>();
         world.register::<ImageIndex>();
         world.register::<Descriptor>();
         world.register::<ImageManifest>();
@@ -184,9 +226,18 @@ impl WebApp for RegistryProxy {
             let host = Arc::new(host);
 
             let route = Route::default()
+                .at(
+                    "/",
+                    get(handle_api_version)
+                        .head(handle_api_version)
+                        .data(self.context.clone()),
+                )
                 .add_route::<Blobs>(&host, &self.context)
                 .add_route::<Manifests>(&host, &self.context)
-                .add_route::<BlobsUploads>(&host, &self.context);
+                .add_route::<BlobsUploads>(&host, &self.context)
+                .add_route::<Tags>(&host, &self.context)
+                .add_route::<Referrers>(&host, &self.context)
+                .add_route::<Catalog>(&host, &self.context);
 
             let token_cache = workspace.work_dir().join("token_cache");
             let token_cache = if token_cache.exists() {
@@ -206,8 +257,95 @@ impl WebApp for RegistryProxy {
             let login_config = LoginConfig::load(root_dir).unwrap_or_default();
             let login_config = Arc::new(RwLock::new(login_config));
 
+            let blob_store = BlobStore::new(workspace.work_dir().join("blobs"));
+
+            let mut manifest_cache = ManifestCache::new(workspace.work_dir().join("manifests"))
+                .with_stale_if_error(self.context.is_enabled("stale_if_error"));
+            if let Some(ttl) = self
+                .context
+                .search()
+                .find_symbol("manifest_ttl")
+                .and_then(|t| t.parse::<u64>().ok())
+            {
+                manifest_cache = manifest_cache.with_ttl(Duration::from_secs(ttl));
+            }
+
+            // Repair the cache in the background, in case a previous process exited while content was being written
+            for store in [blob_store.clone(), manifest_cache.manifests().clone()] {
+                let recover = move || {
+                    if let Err(err) = store.recover() {
+                        error!("Could not recover {:?}, {err}", store.root());
+                    }
+                };
+
+                match tokio::runtime::Handle::try_current() {
+                    Ok(handle) => {
+                        handle.spawn_blocking(recover);
+                    }
+                    Err(_) => recover(),
+                }
+            }
+
+            if let Some(quota) = self
+                .context
+                .search()
+                .find_symbol("cache_quota")
+                .and_then(parse_size)
+            {
+                let interval = self
+                    .context
+                    .search()
+                    .find_symbol("cache_gc_interval")
+                    .and_then(|i| i.parse::<u64>().ok())
+                    .map(Duration::from_secs)
+                    .unwrap_or(DEFAULT_GC_INTERVAL);
+
+                let eviction = Eviction::new(
+                    blob_store.clone(),
+                    manifest_cache.clone(),
+                    workspace.work_dir().join("pins.json"),
+                )
+                .with_quota(quota);
+
+                if eviction.start(interval).is_some() {
+                    info!("Started cache eviction w/ quota {quota} bytes");
+                } else {
+                    warn!("Could not start cache eviction");
+                }
+            }
+
+            let mut peer_list = self
+                .context
+                .search()
+                .find_symbol("peers")
+                .map(|p| p.split(',').map(str::to_string).collect::<Vec<_>>())
+                .unwrap_or_default();
+            if let Some(peer_file) = self.context.search().find_symbol("peer_file") {
+                match Peers::read_peer_file(&peer_file) {
+                    Ok(peers) => peer_list.extend(peers),
+                    Err(err) => warn!("Could not read peer file {peer_file}, {err}"),
+                }
+            }
+            let peers = Peers::new(peer_list, self.context.search().find_symbol("peer_secret"));
+
+            let access_cache = AccessCache::new(
+                self.context
+                    .search()
+                    .find_symbol("access_check_ttl")
+                    .and_then(|t| t.parse::<u64>().ok())
+                    .map(Duration::from_secs)
+                    .unwrap_or(DEFAULT_ACCESS_CHECK_TTL),
+            );
+
             Route::default()
                 .at("/status", get(status_check).data(self.context.clone()))
+                .at(
+                    "/peer/blobs/:digest",
+                    get(handle_peer_blob)
+                        .head(handle_peer_blob)
+                        .data(peers.clone())
+                        .data(blob_store.clone()),
+                )
                 .at(
                     "/auth",
                     get(handle_auth)
@@ -222,7 +360,16 @@ impl WebApp for RegistryProxy {
                         .delete(handle_config.data(self.context.clone())),
                 )
                 .at("/login", put(handle_login).data(login_config.clone()))
-                .nest("/v2", route.data(login_config))
+                .nest(
+                    "/v2",
+                    route
+                        .data(login_config)
+                        .data(blob_store)
+                        .data(manifest_cache)
+                        .data(SingleFlight::default())
+                        .data(peers)
+                        .data(access_cache),
+                )
         } else {
             panic!("Cannot start w/o config")
         }
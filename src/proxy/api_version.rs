@@ -0,0 +1,77 @@
+use hyper::Method;
+use lifec::prelude::ThunkContext;
+use poem::handler;
+use poem::web::{Data, Query};
+use poem::{Request, Response};
+use serde::Deserialize;
+use tracing::{debug, warn};
+
+use crate::consts::{API_VERSION, API_VERSION_HEADER};
+
+/// Query parameters of the api version check,
+///
+#[derive(Deserialize)]
+pub struct ApiVersionQuery {
+    /// Upstream namespace,
+    ///
+    ns: Option<String>,
+}
+
+/// Handles the api version check (`/v2/`),
+///
+/// Clients call this endpoint to detect that the server is a registry and to learn its auth scheme. If upstream passthrough
+/// is enabled and a namespace is passed, the upstream's response is returned so that its auth challenge reaches the client,
+/// otherwise the mirror answers on its own.
+///
+#[handler]
+pub async fn handle_api_version(
+    request: &Request,
+    method: Method,
+    Query(ApiVersionQuery { ns }): Query<ApiVersionQuery>,
+    context: Data<&ThunkContext>,
+) -> Response {
+    if let Some(ns) = ns.filter(|_| context.is_enabled("api_version_passthrough")) {
+        match upstream_api_version(&context, request, &method, &ns).await {
+            Some(response) => return response,
+            None => warn!("Could not check api version of {ns}, answering w/o upstream"),
+        }
+    }
+
+    let response = Response::builder().header(API_VERSION_HEADER, API_VERSION);
+    if method == Method::HEAD {
+        response.finish()
+    } else {
+        response.content_type("application/json").body("{}")
+    }
+}
+
+/// Returns the upstream's response to the api version check, w/ the api version header set,
+///
+async fn upstream_api_version(
+    context: &ThunkContext,
+    request: &Request,
+    method: &Method,
+    ns: &str,
+) -> Option<Response> {
+    let client = context.client()?;
+
+    let mut upstream = hyper::Request::builder()
+        .method(method)
+        .uri(format!("https://{ns}/v2/"));
+    if let Some(authorization) = request.header("authorization") {
+        upstream = upstream.header("authorization", authorization);
+    }
+
+    match client.request(upstream.body(hyper::Body::empty()).ok()?).await {
+        Ok(response) => {
+            debug!("Upstream {ns} returned {} for api version check", response.status());
+            let mut response: Response = response.into();
+            response.headers_mut().insert(API_VERSION_HEADER, API_VERSION.parse().ok()?);
+            Some(response)
+        }
+        Err(err) => {
+            warn!("Could not reach {ns}, {err}");
+            None
+        }
+    }
+}
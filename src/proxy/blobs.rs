@@ -1,4 +1,4 @@
-use super::proxy_route::RouteParameters;
+use super::proxy_route::{LocalContent, RouteParameters};
 
 /// Route plugin to handle registry download blob requests,
 ///
@@ -16,10 +16,14 @@ pub struct Blobs;
 
 impl RouteParameters for Blobs {
     fn path() -> &'static str {
-        "/:repo<[a-zA-Z0-9/_-]+(?:blobs)>/:reference"
+        concat!(repo_path!("blobs"), "/:reference<^", digest_regex!(), "$>")
     }
 
     fn ident() -> &'static str {
         "blobs"
     }
+
+    fn local_content() -> LocalContent {
+        LocalContent::Blobs
+    }
 }
\ No newline at end of file
@@ -2,6 +2,22 @@ use super::proxy_route::RouteParameters;
 
 /// Route plugin to handle registry blob uploads,
 ///
+/// Handles starting an upload session (including cross-repo mounts w/ `?mount=<digest>&from=<repo>`), chunked and
+/// monolithic uploads, upload status, and canceling an upload. Request bodies are streamed to the upstream by the mirror,
+/// so operations for methods w/ a body should only authenticate, Ex. `.login` and `.authn` w/o `.request`.
+///
+/// ```markdown
+/// | ID     | Method         | API Endpoint                                                 | Success     | Failure           |
+/// | ------ | -------------- | ------------------------------------------------------------ | ----------- | ----------------- |
+/// | end-4a | `POST`         | `/v2/<name>/blobs/uploads/`                                  | `202`       | `404`             |
+/// | end-4b | `POST`         | `/v2/<name>/blobs/uploads/?digest=<digest>`                  | `201`/`202` | `404`/`400`       |
+/// | end-5  | `PATCH`        | `/v2/<name>/blobs/uploads/<reference>`                       | `202`       | `404`/`416`       |
+/// | end-6  | `PUT`          | `/v2/<name>/blobs/uploads/<reference>?digest=<digest>`       | `201`       | `404`/`400`       |
+/// | end-11 | `POST`         | `/v2/<name>/blobs/uploads/?mount=<digest>&from=<other_name>` | `201`       | `404`             |
+/// | end-13 | `GET`          | `/v2/<name>/blobs/uploads/<reference>`                       | `204`       | `404`             |
+/// |        | `DELETE`       | `/v2/<name>/blobs/uploads/<reference>`                       | `204`       | `404`             |
+/// ```
+///
 /// Example:
 /// : .mirror     <azurecr.io>
 /// : .host       <address> resolve, push
@@ -9,16 +25,28 @@ use super::proxy_route::RouteParameters;
 /// + .proxy      <address>
 /// : .blobs_uploads
 /// : .post        <operation-name>
+/// : .patch       <operation-name>
+/// : .put         <operation-name>
+/// : .delete      <operation-name>
+/// : .get         <operation-name>
 ///
 #[derive(Default, Clone)]
 pub struct BlobsUploads;
 
 impl RouteParameters for BlobsUploads {
     fn path() -> &'static str {
-        "/:repo<[a-zA-Z0-9/_-]+(?:blobs/uploads)>/"
+        concat!(repo_path!("blobs/uploads"), "/")
     }
 
     fn ident() -> &'static str {
         "blobs_uploads"
     }
+
+    fn alternate_paths() -> &'static [&'static str] {
+        &[concat!(repo_path!("blobs/uploads"), "/:reference")]
+    }
+
+    fn streams_body() -> bool {
+        true
+    }
 }
\ No newline at end of file
@@ -0,0 +1,137 @@
+use std::collections::BTreeSet;
+
+use poem::Response;
+use serde::{Deserialize, Serialize};
+
+use crate::content::CacheIndex;
+use crate::{BlobStore, ManifestCache};
+
+use super::proxy_route::{LocalContent, RouteParameters};
+
+/// Default number of repositories returned by the catalog fallback,
+///
+pub const DEFAULT_CATALOG_PAGE_SIZE: usize = 100;
+
+/// Route plugin to handle registry catalog requests,
+///
+/// The `n` and `last` query parameters are forwarded to the upstream, and the `Link` header of the upstream response is
+/// rewritten so that clients continue paging through the mirror. If the upstream does not support the catalog and
+/// `catalog_fallback` is enabled, the repositories of the namespace that content was cached from are returned instead.
+/// The fallback is not authorized w/ the upstream, so it lists repositories cached by any caller of the mirror.
+///
+/// Example:
+/// : .mirror     <azurecr.io>
+/// : .host       <address> resolve, pull
+///
+/// + .proxy      <address>
+/// : .catalog
+/// : .get        <operation-name>
+///
+#[derive(Default, Clone)]
+pub struct Catalog;
+
+impl RouteParameters for Catalog {
+    fn path() -> &'static str {
+        "/_catalog"
+    }
+
+    fn ident() -> &'static str {
+        "catalog"
+    }
+
+    fn local_content() -> LocalContent {
+        LocalContent::Catalog
+    }
+
+    fn upstream_path(_: &str, _: &str) -> String {
+        "_catalog".to_string()
+    }
+}
+
+/// Query parameters for paging through the catalog,
+///
+#[derive(Default, Deserialize)]
+struct CatalogQuery {
+    /// Maximum number of repositories to return,
+    ///
+    n: Option<usize>,
+    /// Last repository of the previous page,
+    ///
+    last: Option<String>,
+}
+
+/// Body of a catalog response,
+///
+#[derive(Serialize)]
+struct CatalogList {
+    /// Repository names in lexical order,
+    ///
+    repositories: Vec<String>,
+}
+
+/// Returns a catalog response w/ the repositories of a namespace that blobs or manifests were cached from,
+///
+pub fn seen_catalog(
+    blob_store: &BlobStore,
+    manifest_cache: &ManifestCache,
+    ns: &str,
+    query: Option<&str>,
+) -> Response {
+    let CatalogQuery { n, last } = query
+        .and_then(|q| serde_urlencoded::from_str::<CatalogQuery>(q).ok())
+        .unwrap_or_default();
+
+    let prefix = CacheIndex::source(ns, "");
+    let repositories = blob_store
+        .index()
+        .entries()
+        .into_values()
+        .chain(manifest_cache.manifests().index().entries().into_values())
+        .flat_map(|e| e.sources)
+        .filter_map(|s| s.strip_prefix(&prefix).map(str::to_string))
+        .collect::<BTreeSet<_>>();
+
+    let (repositories, next) = page(repositories, n.unwrap_or(DEFAULT_CATALOG_PAGE_SIZE), last.as_deref());
+
+    let mut response = Response::builder().content_type("application/json");
+    if let Some(next) = next {
+        response = response.header(
+            "link",
+            format!(r#"</v2/_catalog?n={}&last={next}&ns={ns}>; rel="next""#, n.unwrap_or(DEFAULT_CATALOG_PAGE_SIZE)),
+        );
+    }
+
+    response.body(serde_json::to_vec(&CatalogList { repositories }).unwrap_or_default())
+}
+
+/// Returns a page of repositories after `last`, and the last repository of the page if there are more,
+///
+fn page(repositories: BTreeSet<String>, n: usize, last: Option<&str>) -> (Vec<String>, Option<String>) {
+    let mut remaining = repositories
+        .into_iter()
+        .filter(|r| last.map_or(true, |last| r.as_str() > last));
+
+    let page = remaining.by_ref().take(n).collect::<Vec<_>>();
+    let next = page.last().cloned().filter(|_| remaining.next().is_some());
+
+    (page, next)
+}
+
+#[allow(unused_imports)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::page;
+
+    #[test]
+    fn test_catalog_page() {
+        let repositories = ["a", "b", "c"].into_iter().map(str::to_string).collect::<BTreeSet<_>>();
+
+        assert_eq!(
+            (vec!["a".to_string(), "b".to_string()], Some("b".to_string())),
+            page(repositories.clone(), 2, None)
+        );
+        assert_eq!((vec!["c".to_string()], None), page(repositories.clone(), 2, Some("b")));
+        assert_eq!((vec![], None), page(repositories, 2, Some("c")));
+    }
+}
@@ -0,0 +1,122 @@
+use hyper::Uri;
+
+/// Rewrites the value of an upstream `Link` header so that the link points back to the mirror,
+///
+/// Registries return pagination links as either a path or an absolute url on the upstream host, Ex.
+/// `</v2/library/hello/tags/list?n=10&last=v1>; rel="next"`. The link is rewritten to a path on the mirror w/ the `ns`
+/// query parameter, so that the next page is requested from the same upstream namespace.
+///
+pub fn rewrite_link(link: impl AsRef<str>, ns: impl AsRef<str>, tenant: Option<&str>) -> Option<String> {
+    let link = link.as_ref().trim();
+    let (target, params) = link.strip_prefix('<')?.split_once('>')?;
+
+    Some(format!("<{}>{params}", rewrite_location(target, ns, tenant)?))
+}
+
+/// Rewrites the value of an upstream `Location` header so that it points back to the mirror,
+///
+/// Only locations of registry apis on the upstream are rewritten, Ex. upload sessions. Locations on other hosts, such as
+/// redirects to blob storage, are left as is.
+///
+/// If the request used the tenant workaround, the upstream is `<tenant>.<ns>`, and the `_tenant_<tenant>/` prefix is
+/// added back to the repository in the path, so that the next request is proxied to the same upstream.
+///
+pub fn rewrite_location(location: impl AsRef<str>, ns: impl AsRef<str>, tenant: Option<&str>) -> Option<String> {
+    let ns = ns.as_ref();
+    let upstream = match tenant {
+        Some(tenant) => format!("{tenant}.{ns}"),
+        None => ns.to_string(),
+    };
+
+    let location = location.as_ref().parse::<Uri>().ok()?;
+    if location.host().map_or(false, |host| host != upstream) {
+        return None;
+    }
+
+    let path_and_query = location.path_and_query()?;
+    let path = match (path_and_query.path().strip_prefix("/v2/"), tenant) {
+        (Some(path), Some(tenant)) => format!("/v2/_tenant_{tenant}/{path}"),
+        (Some(path), None) => format!("/v2/{path}"),
+        (None, _) => return None,
+    };
+
+    let mut query = path_and_query
+        .query()
+        .map(|q| {
+            q.split('&')
+                .filter(|p| !p.is_empty() && !p.starts_with("ns="))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    let ns = format!("ns={ns}");
+    query.push(&ns);
+
+    Some(format!("{path}?{}", query.join("&")))
+}
+
+#[allow(unused_imports)]
+mod tests {
+    use super::{rewrite_link, rewrite_location};
+
+    #[test]
+    fn test_rewrite_link() {
+        assert_eq!(
+            Some(r#"</v2/library/hello/tags/list?n=10&last=v1&ns=test.azurecr.io>; rel="next""#.to_string()),
+            rewrite_link(
+                r#"</v2/library/hello/tags/list?n=10&last=v1>; rel="next""#,
+                "test.azurecr.io",
+                None
+            )
+        );
+
+        assert_eq!(
+            Some(r#"</v2/library/hello/tags/list?last=v1&ns=test.azurecr.io>; rel="next""#.to_string()),
+            rewrite_link(
+                r#"<https://test.azurecr.io/v2/library/hello/tags/list?last=v1&ns=other.azurecr.io>; rel="next""#,
+                "test.azurecr.io",
+                None
+            )
+        );
+
+        assert_eq!(
+            Some(r#"</v2/_tenant_t1/hello/tags/list?last=v1&ns=azurecr.io>; rel="next""#.to_string()),
+            rewrite_link(r#"</v2/hello/tags/list?last=v1>; rel="next""#, "azurecr.io", Some("t1"))
+        );
+
+        assert_eq!(None, rewrite_link("not a link", "test.azurecr.io", None));
+        assert_eq!(None, rewrite_link("</oauth2/token>", "test.azurecr.io", None));
+    }
+
+    #[test]
+    fn test_rewrite_location() {
+        assert_eq!(
+            Some("/v2/hello/blobs/uploads/1234?_state=abc&ns=test.azurecr.io".to_string()),
+            rewrite_location("/v2/hello/blobs/uploads/1234?_state=abc", "test.azurecr.io", None)
+        );
+        assert_eq!(
+            Some("/v2/hello/blobs/sha256:abc?ns=test.azurecr.io".to_string()),
+            rewrite_location("https://test.azurecr.io/v2/hello/blobs/sha256:abc", "test.azurecr.io", None)
+        );
+
+        // Requests that used the tenant workaround continue to use it
+        assert_eq!(
+            Some("/v2/_tenant_t1/hello/blobs/uploads/1234?_state=abc&ns=azurecr.io".to_string()),
+            rewrite_location("/v2/hello/blobs/uploads/1234?_state=abc", "azurecr.io", Some("t1"))
+        );
+        assert_eq!(
+            Some("/v2/_tenant_t1/hello/blobs/uploads/1234?ns=azurecr.io".to_string()),
+            rewrite_location("https://t1.azurecr.io/v2/hello/blobs/uploads/1234", "azurecr.io", Some("t1"))
+        );
+        assert_eq!(
+            None,
+            rewrite_location("https://azurecr.io/v2/hello/blobs/uploads/1234", "azurecr.io", Some("t1"))
+        );
+
+        // Redirects to other hosts are not rewritten
+        assert_eq!(
+            None,
+            rewrite_location("https://storage.example.com/v2/hello/blobs/sha256:abc", "test.azurecr.io", None)
+        );
+    }
+}
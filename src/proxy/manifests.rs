@@ -1,7 +1,11 @@
-use super::proxy_route::RouteParameters;
+use super::proxy_route::{LocalContent, RouteParameters};
 
 /// Route plugin to handle registry manifest requests,
 ///
+/// Manifest pushes and deletes are streamed to the upstream by the mirror w/ the original `Content-Type`, so operations for
+/// `.put` and `.delete` should only authenticate, Ex. `.login` and `.authn` w/o `.request`. When a push or delete succeeds,
+/// cached entries for the reference are invalidated.
+///
 /// Example:
 /// : .mirror     <azurecr.io>
 /// : .host       <address> resolve, push
@@ -10,17 +14,27 @@ use super::proxy_route::RouteParameters;
 /// : .manifests  
 /// : .get        <operation-name>
 /// : .head       <operation-name>
+/// : .put        <operation-name>
+/// : .delete     <operation-name>
 ///
 #[derive(Default, Clone)]
 pub struct Manifests;
 
 impl RouteParameters for Manifests {
     fn path() -> &'static str {
-        "/:repo<[a-zA-Z0-9/_-]+(?:manifests)>/:reference"
+        concat!(repo_path!("manifests"), "/:reference<^(?:", tag_regex!(), "|", digest_regex!(), ")$>")
     }
 
     fn ident() -> &'static str {
         "manifests"
     }
+
+    fn local_content() -> LocalContent {
+        LocalContent::Manifests
+    }
+
+    fn streams_body() -> bool {
+        true
+    }
 }
 
@@ -0,0 +1,34 @@
+use hyper::{Method, StatusCode};
+use poem::handler;
+use poem::web::{Data, Path};
+use poem::{Request, Response};
+use tracing::debug;
+
+use crate::content::PEER_TOKEN_HEADER;
+use crate::{BlobStore, Peers, Registry};
+
+/// Serves local blobs to peer mirrors,
+///
+/// Only content that is already in the local blob store is served, this route never goes to the upstream or other peers,
+///
+#[handler]
+pub async fn handle_peer_blob(
+    request: &Request,
+    method: Method,
+    Path(digest): Path<String>,
+    peers: Data<&Peers>,
+    blob_store: Data<&BlobStore>,
+) -> Response {
+    if !peers.authorize(&digest, request.header(PEER_TOKEN_HEADER)) {
+        debug!("Rejecting peer request for {digest}");
+        return Response::builder().status(StatusCode::UNAUTHORIZED).finish();
+    }
+
+    match blob_store.get(&digest).await {
+        Some(local) => {
+            let range = request.header("range").filter(|_| method == Method::GET);
+            Registry.serve_local(&method, local, range).await
+        }
+        None => Response::builder().status(StatusCode::NOT_FOUND).finish(),
+    }
+}
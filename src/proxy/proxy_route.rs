@@ -1,19 +1,23 @@
-use std::{sync::Arc, marker::PhantomData};
+use std::{sync::Arc, marker::PhantomData, collections::{BTreeSet, HashMap}};
 
-use hyper::Method;
+use hyper::{Method, StatusCode};
 use lifec::prelude::{AttributeParser, Host, SpecialAttribute, Value, ThunkContext};
 use lifec_poem::RoutePlugin;
 use poem::{
-    delete, get, handler, head, put, post,
-    web::{Data, Path, Query},
+    handler,
+    web::{Data, Query},
     EndpointExt, Response, RouteMethod, Body, 
+    http::HeaderValue,
 };
 use serde::{Deserialize, Serialize};
-use specs::{Component, VecStorage, WorldExt, Join};
+use specs::{Component, Entity, VecStorage, WorldExt, Join};
 use tokio::sync::RwLock;
 use tracing::{event, Level};
 
-use crate::{Registry, config::LoginConfig};
+use super::catalog;
+use super::link::{rewrite_link, rewrite_location};
+use crate::content::UpstreamApi;
+use crate::{Digest, Registry, BlobStore, ManifestCache, SingleFlight, Peers, AccessCache, config::LoginConfig, content::CacheIndex};
 
 /// Trait to include a specific route to the proxy,
 /// 
@@ -25,6 +29,50 @@ pub trait RouteParameters: Default + Clone + Send + Sync + 'static {
     /// Returns the resource ident for this route,
     /// 
     fn ident() -> &'static str;
+
+    /// Returns the kind of local content this route can be served from,
+    /// 
+    fn local_content() -> LocalContent {
+        LocalContent::None
+    }
+
+    /// Returns additional paths this route is served on,
+    /// 
+    fn alternate_paths() -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Returns the path of the upstream api relative to `/v2/`,
+    /// 
+    fn upstream_path(repo: &str, reference: &str) -> String {
+        // Idents w/ an underscore map to nested resources, Ex. blobs_uploads -> blobs/uploads
+        format!("{repo}/{}/{reference}", Self::ident().replace('_', "/"))
+    }
+
+    /// Returns true if request bodies are streamed to the upstream by the mirror, see `UpstreamApi::streams_body`,
+    /// 
+    fn streams_body() -> bool {
+        false
+    }
+}
+
+/// Enumeration of local content a route can be served from before contacting the upstream server,
+/// 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LocalContent {
+    /// Route is always proxied,
+    /// 
+    None,
+    /// Route serves digest-addressed blobs from the local blob store,
+    /// 
+    Blobs,
+    /// Route serves manifests from the manifest cache,
+    /// 
+    Manifests,
+    /// Route serves the repositories recorded in the cache index, if the upstream does not support the route and
+    /// `catalog_fallback` is enabled,
+    /// 
+    Catalog,
 }
 
 /// Trait for a fn that adds a new proxy route to an app,
@@ -42,22 +90,52 @@ impl AddRoute for poem::Route {
     where 
         R: RouteParameters
     {
-        let mut proxy_route = None::<RouteMethod>;
-        for r in host.world().read_component::<ProxyRoute<R>>().join() {
-            if r.can_route() {
-                let mut r = r.clone();
-                r.set_context(context.clone());
-
-                if let Some(m) = proxy_route.take() {
-                    proxy_route = Some(r.route(Some(m)));
-                } else {
-                    proxy_route = Some(r.route(None));
+        // Requests w/ the upgrade header use the operation of a tagged workspace, Ex. `+ overlaybd .operation ..`
+        let tags = host
+            .world()
+            .try_fetch::<HashMap<String, Entity>>()
+            .map(|operations| {
+                operations
+                    .keys()
+                    .filter_map(|k| k.split_once('#').map(|(_, tag)| tag.to_string()))
+                    .collect::<BTreeSet<_>>()
+            })
+            .unwrap_or_default();
+
+        for path in std::iter::once(R::path()).chain(R::alternate_paths().iter().copied()) {
+            let mut proxy_route = None::<RouteMethod>;
+            for r in host.world().read_component::<ProxyRoute<R>>().join() {
+                if r.can_route() {
+                    // Report operations that do not exist now, rather than when a request is handled
+                    let operation = r.operation.as_deref().unwrap_or_default();
+                    let missing = context.workspace().and_then(|w| {
+                        std::iter::once(None).chain(tags.iter().map(Some)).find(|tag| {
+                            let w = match tag {
+                                Some(tag) => w.use_tag(tag),
+                                None => w.to_owned(),
+                            };
+                            w.find_operation(operation).is_none()
+                        })
+                    });
+                    if let Some(tag) = missing {
+                        event!(Level::ERROR, "Operation `{operation}` for `.{}` was not found w/ tag {:?}, skipping {:?} {path}", R::ident(), tag, r.method);
+                        continue;
+                    }
+
+                    let mut r = r.clone();
+                    r.set_context(context.clone());
+
+                    if let Some(m) = proxy_route.take() {
+                        proxy_route = Some(r.route(Some(m)));
+                    } else {
+                        proxy_route = Some(r.route(None));
+                    }
                 }
             }
-        }
-        let path = R::path();
-        if let Some(proxy_route) = proxy_route.take() {
-            self = self.at(path, proxy_route);
+
+            if let Some(proxy_route) = proxy_route.take() {
+                self = self.at(path, proxy_route);
+            }
         }
 
         self
@@ -107,6 +185,83 @@ impl<R: RouteParameters> ProxyRoute<R> {
     fn set_context(&mut self, context: ThunkContext) {
         self.context = context;
     }
+
+    /// Checks that local content can be served to the requested repository, returns the response to deny the request w/,
+    ///
+    /// Content fetched from another repository is only served if the caller can read it from this repository, which is
+    /// checked w/ an authorized HEAD request to the upstream. The result of the check is cached in the access cache, and
+    /// is not recorded as a source of the content.
+    ///
+    async fn check_access(
+        &self,
+        index: &CacheIndex,
+        request: &poem::Request,
+        registry: &Registry,
+        context: &ThunkContext,
+        login_config: &Arc<RwLock<LoginConfig>>,
+        access_cache: &AccessCache,
+        ns: &str,
+        repo: &str,
+        digest: &str,
+    ) -> Option<Response> {
+        let source = CacheIndex::source(ns, repo);
+        let fetched_from_source = index
+            .get(digest)
+            .map(|e| e.sources.contains(&source))
+            .unwrap_or_default();
+
+        if fetched_from_source {
+            return None;
+        }
+
+        let key = AccessCache::key(&source, digest, request.header("authorization"));
+        let status = match access_cache.get(&key) {
+            Some(status) => status,
+            None => {
+                event!(Level::DEBUG, "Checking access to {digest} in {source}");
+                let mut check = poem::Request::builder()
+                    .method(Method::HEAD)
+                    .uri(request.uri().clone())
+                    .finish();
+                *check.headers_mut() = request.headers().clone();
+                check.headers_mut().remove("range");
+
+                let status = registry
+                    .proxy_request::<Self>(
+                        context,
+                        self.operation
+                            .clone()
+                            .expect("should have an operation name"),
+                        &check,
+                        None,
+                        ns,
+                        repo,
+                        Some(digest),
+                        login_config.clone()
+                    ).await
+                    .status();
+                access_cache.insert(key, status);
+                status
+            }
+        };
+
+        if AccessCache::is_allowed(status) {
+            None
+        } else {
+            event!(Level::WARN, "Denied access to {digest} in {source}, upstream returned {status}");
+            Some(Response::builder().status(status).finish())
+        }
+    }
+}
+
+impl<R: RouteParameters> UpstreamApi for ProxyRoute<R> {
+    fn upstream_path(repo: &str, reference: &str) -> String {
+        R::upstream_path(repo, reference)
+    }
+
+    fn streams_body() -> bool {
+        R::streams_body()
+    }
 }
 
 impl<R: RouteParameters> SpecialAttribute for ProxyRoute<R> {
@@ -136,6 +291,10 @@ impl<R: RouteParameters> SpecialAttribute for ProxyRoute<R> {
         add::<R, PUTKEY>(parser);
         add::<R, HEADKEY>(parser);
         add::<R, DELETEKEY>(parser);
+        add::<R, PATCHKEY>(parser);
+        add::<R, OPTIONSKEY>(parser);
+        add::<R, CONNECTKEY>(parser);
+        add::<R, TRACEKEY>(parser);
     }
 }
 
@@ -169,14 +328,33 @@ fn ident<const METHODKEY: usize>() -> &'static str {
     }
 }
 
+/// Parses a method attribute for a route, configuration errors are logged and the method is skipped,
+/// 
 fn parse<R: RouteParameters, const METHODKEY: usize>(p: &mut AttributeParser, c: String) {
-        let last_entity = p.last_child_entity().expect("should have an entity");
-        let world = p.world().expect("should have a world");
+        let method = ident::<METHODKEY>();
+        let operation = c.trim().to_string();
+        if operation.is_empty() {
+            event!(Level::ERROR, "`.{method}` under `.{}` requires an operation name, skipping", R::ident());
+            return;
+        }
+
+        let (last_entity, world) = match (p.last_child_entity(), p.world()) {
+            (Some(last_entity), Some(world)) => (last_entity, world),
+            _ => {
+                event!(Level::ERROR, "`.{method} {operation}` must follow a route attribute, Ex. `.{}`, skipping", R::ident());
+                return;
+            }
+        };
 
         let route = {
             let route = world.read_component::<ProxyRoute<R>>();
-            let route = route.get(last_entity).expect("should have a route component");
-            route.clone()
+            match route.get(last_entity) {
+                Some(route) => route.clone(),
+                None => {
+                    event!(Level::ERROR, "`.{method} {operation}` must follow `.{}`, skipping", R::ident());
+                    return;
+                }
+            }
         };
 
         let mut route = route.clone();
@@ -192,7 +370,7 @@ fn parse<R: RouteParameters, const METHODKEY: usize>(p: &mut AttributeParser, c:
             TRACEKEY => Some(Method::TRACE),
             _ => None
         };
-        route.operation = Some(c);
+        route.operation = Some(operation);
         let route_entity = world.entities().create();
         world
             .write_component()
@@ -201,64 +379,22 @@ fn parse<R: RouteParameters, const METHODKEY: usize>(p: &mut AttributeParser, c:
 }
 
 impl<R: RouteParameters> RoutePlugin for ProxyRoute<R> {
-    fn route(&self, mut route: Option<RouteMethod>) -> RouteMethod {
+    fn route(&self, route: Option<RouteMethod>) -> RouteMethod {
         let path = R::path();
         let api = proxy_api::<R>::default()
             .data(self.clone())
             .data(Registry::default())
             .data(self.context.clone());
 
-        if let Some(route) = route.take() {
-            match self.method {
-                Some(Method::GET) => {
-                    event!(Level::DEBUG, "adding path GET {path}");
-                    route.get(api)
-                }
-                Some(Method::POST) => {
-                    event!(Level::DEBUG, "adding path POST {path}");
-                    route.post(api)
-                }
-                Some(Method::PUT) => {
-                    event!(Level::DEBUG, "adding path PUT {path}");
-                    route.put(api)
-                }
-                Some(Method::HEAD) => {
-                    event!(Level::DEBUG, "adding path HEAD {path}");
-                    route.head(api)
-                }
-                Some(Method::DELETE) => {
-                    event!(Level::DEBUG, "adding path DELETE {path}");
-                    route.delete(api)
-                }
-                _ => {
-                    unimplemented!("Unsupported method, {:?}", self.method)
-                }
+        let route = route.unwrap_or_default();
+        match self.method.clone() {
+            Some(method) => {
+                event!(Level::DEBUG, "adding path {method} {path}");
+                route.method(method, api)
             }
-        } else {
-            match self.method {
-                Some(Method::GET) => {
-                    event!(Level::DEBUG, "adding path GET {path}");
-                    get(api)
-                }
-                Some(Method::POST) => {
-                    event!(Level::DEBUG, "adding path POST {path}");
-                    post(api)
-                }
-                Some(Method::PUT) => {
-                    event!(Level::DEBUG, "adding path PUT {path}");
-                    put(api)
-                }
-                Some(Method::HEAD) => {
-                    event!(Level::DEBUG, "adding path HEAD {path}");
-                    head(api)
-                }
-                Some(Method::DELETE) => {
-                    event!(Level::DEBUG, "adding path DELETE {path}");
-                    delete(api)
-                }
-                _ => {
-                    unimplemented!("Unsupported method, {:?}", self.method)
-                }
+            None => {
+                event!(Level::ERROR, "Proxy route for {path} does not have a method, skipping");
+                route
             }
         }
     }
@@ -279,17 +415,139 @@ impl<R: RouteParameters> RoutePlugin for ProxyRoute<R> {
 async fn proxy_api<R>(
     request: &poem::Request,
     body: Body,
-    Path((repo, reference)): Path<(String, Option<String>)>,
     Query(ProxyRoute { ns, .. }): Query<ProxyRoute<R>>,
     resolve: Data<&ProxyRoute<R>>,
     registry: Data<&Registry>,
     context: Data<&ThunkContext>,
     login_config: Data<&Arc<RwLock<LoginConfig>>>,
+    blob_store: Data<&BlobStore>,
+    manifest_cache: Data<&ManifestCache>,
+    single_flight: Data<&SingleFlight>,
+    peers: Data<&Peers>,
+    access_cache: Data<&AccessCache>,
 ) -> Response 
 where
     R: RouteParameters
 { 
-    registry
+    // Not every route has a repo or reference, Ex. the catalog
+    let repo = request.raw_path_param("repo").unwrap_or_default();
+    let resource = format!("/{}", R::ident().replace('_', "/"));
+    let repo = repo.strip_suffix(resource.as_str()).unwrap_or(repo);
+    let reference = request.raw_path_param("reference").filter(|r| !r.is_empty()).map(str::to_string);
+    let method = request.method().clone();
+
+    // A reference w/ an algorithm prefix must be a valid digest, tags cannot contain a `:`
+    let digest = match reference.as_ref().filter(|r| r.contains(':')).map(Digest::parse) {
+        Some(Ok(digest)) => Some(digest),
+        Some(Err(err)) => {
+            event!(Level::DEBUG, "Rejecting invalid digest {:?}, {err}", reference);
+            return Registry::error_response(StatusCode::BAD_REQUEST, "DIGEST_INVALID", "invalid digest");
+        }
+        None => None,
+    };
+    let is_digest = digest.is_some();
+
+    // Range requests are forwarded upstream w/ the rest of the request headers, and are also served from local content
+    let range = request.header("range").filter(|_| method == Method::GET);
+
+    // Only reads can be served from local content
+    let local_content = if method == Method::GET || method == Method::HEAD {
+        R::local_content()
+    } else {
+        LocalContent::None
+    };
+
+    let tag_key = reference
+        .as_ref()
+        .filter(|_| local_content == LocalContent::Manifests && !is_digest)
+        .map(|r| {
+            ManifestCache::key(
+                &ns,
+                repo,
+                r,
+                request.header(crate::consts::UPGRADE_IF_STREAMABLE_HEADER),
+            )
+        });
+
+    // Held until the upstream response has been stored, so that concurrent requests for the same blob are served locally
+    let mut leader = None;
+
+    match (local_content, reference.as_ref()) {
+        (LocalContent::Blobs, Some(digest)) if is_digest => {
+            let mut local = blob_store.get(digest).await;
+
+            if local.is_none() && method == Method::GET {
+                // If a leader could not store the blob, only one of the waiting requests fetches it next
+                match single_flight.wait_or_lead(SingleFlight::key(&ns, digest), || blob_store.get(digest)).await {
+                    Ok(stored) => local = Some(stored),
+                    Err(guard) => {
+                        // Other mirrors in the cluster may already have the blob
+                        local = peers.fetch(&blob_store, digest, CacheIndex::source(&ns, repo)).await;
+                        leader = Some(guard);
+                    }
+                }
+            }
+
+            if let Some(local) = local {
+                let denied = resolve
+                    .check_access(
+                        blob_store.index(),
+                        request,
+                        &registry,
+                        &context,
+                        &login_config,
+                        &access_cache,
+                        &ns,
+                        repo,
+                        digest,
+                    )
+                    .await;
+                if let Some(denied) = denied {
+                    return denied;
+                }
+
+                event!(Level::DEBUG, "Serving {digest} from local blob store");
+                blob_store.index().touch(digest, None).await.ok();
+                return registry.serve_local(&method, local, range).await;
+            }
+        }
+        (LocalContent::Manifests, Some(reference)) => {
+            let cached = match tag_key.as_ref() {
+                Some(key) => manifest_cache.get_tag(key, false).await,
+                None => manifest_cache.get_digest(reference).await,
+            };
+
+            if let Some(cached) = cached.filter(|c| c.accepted_by(request.header("accept"))) {
+                // Tags are cached per repository, but manifests fetched by digest can be shared w/ other repositories
+                if tag_key.is_none() {
+                    let denied = resolve
+                        .check_access(
+                            manifest_cache.manifests().index(),
+                            request,
+                            &registry,
+                            &context,
+                            &login_config,
+                            &access_cache,
+                            &ns,
+                            repo,
+                            reference,
+                        )
+                        .await;
+                    if let Some(denied) = denied {
+                        return denied;
+                    }
+                }
+
+                event!(Level::DEBUG, "Serving {reference} from manifest cache");
+                return registry.serve_manifest(&method, cached);
+            }
+        }
+        _ => {}
+    }
+
+    let source = CacheIndex::source(&ns, repo);
+    let upstream_ns = ns.clone();
+    let response = registry
         .proxy_request::<ProxyRoute<R>>(
             &context,
             resolve
@@ -297,10 +555,176 @@ where
                 .clone()
                 .expect("should have an operation name"),
             request,
-            Some(body.into()),
+            // Reads do not have a body, so there is nothing to cache
+            Some(body)
+                .filter(|_| method != Method::GET && method != Method::HEAD)
+                .map(Into::into),
             ns,
-            repo.trim_end_matches(R::ident().replace("_", "/").as_str()).trim_end_matches("/"),
-            reference.filter(|r| !r.is_empty()),
+            repo,
+            reference.as_ref(),
             login_config.clone()
-        ).await
+        ).await;
+
+    // Pagination links and upload locations point at the upstream, so they are rewritten to continue through the mirror
+    let tenant = Registry::split_tenant(repo).map(|(tenant, _)| tenant);
+    let response = rewrite_headers(response, &upstream_ns, tenant);
+
+    // Pushes and deletes change what the repository resolves to, so cached entries are dropped
+    if R::local_content() == LocalContent::Manifests
+        && (method == Method::PUT || method == Method::DELETE)
+        && response.status().is_success()
+    {
+        if let Some(reference) = reference.as_ref() {
+            match manifest_cache.invalidate(&upstream_ns, repo, reference).await {
+                Ok(removed) => event!(Level::DEBUG, "Invalidated {removed} cached tags for {repo}, {reference}"),
+                Err(err) => event!(Level::ERROR, "Could not invalidate manifest cache for {repo}, {reference}, {err}"),
+            }
+        }
+    }
+
+    match (local_content, reference) {
+        (LocalContent::Blobs, Some(digest))
+            if is_digest && method == Method::GET && response.status() == StatusCode::OK =>
+        {
+            // A 206 from an upstream that honored the range is passed through, since partial content cannot be stored
+            registry.store_local(&blob_store, source, digest, range, response, leader.take()).await
+        }
+        (LocalContent::Manifests, Some(reference)) => {
+            registry
+                .cache_manifest(
+                    &manifest_cache,
+                    source,
+                    tag_key,
+                    reference,
+                    &method,
+                    request.header("accept"),
+                    response,
+                )
+                .await
+        }
+        // The cache index is not authorized w/ the upstream, so listing it must be enabled by the mirror's config
+        (LocalContent::Catalog, _)
+            if matches!(response.status(), StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED)
+                && context.is_enabled("catalog_fallback") =>
+        {
+            event!(Level::DEBUG, "Upstream returned {}, serving catalog from cache index", response.status());
+            catalog::seen_catalog(&blob_store, &manifest_cache, &upstream_ns, request.uri().query())
+        }
+        _ => response,
+    }
+}
+
+/// Rewrites the `Link` and `Location` headers of an upstream response so that clients continue through the mirror,
+/// 
+fn rewrite_headers(mut response: Response, ns: &str, tenant: Option<&str>) -> Response {
+    let link = response
+        .headers()
+        .get("link")
+        .and_then(|l| l.to_str().ok())
+        .and_then(|l| rewrite_link(l, ns, tenant))
+        .and_then(|l| HeaderValue::from_str(&l).ok());
+
+    if let Some(link) = link {
+        response.headers_mut().insert("link", link);
+    }
+
+    let location = response
+        .headers()
+        .get("location")
+        .and_then(|l| l.to_str().ok())
+        .and_then(|l| rewrite_location(l, ns, tenant))
+        .and_then(|l| HeaderValue::from_str(&l).ok());
+
+    if let Some(location) = location {
+        response.headers_mut().insert("location", location);
+    }
+
+    response
+}
+
+#[allow(unused_imports)]
+mod tests {
+    use hyper::StatusCode;
+    use poem::{get, handler, Request, Route};
+
+    use super::RouteParameters;
+    use crate::proxy::{Blobs, Manifests};
+
+    /// Echoes the repo parameter the same way the proxy api extracts it,
+    ///
+    #[handler]
+    fn echo(request: &Request) -> String {
+        let repo = request.raw_path_param("repo").unwrap_or_default();
+        let repo = repo.strip_suffix("/manifests").or_else(|| repo.strip_suffix("/blobs")).unwrap_or(repo);
+        format!("{repo} {}", request.raw_path_param("reference").unwrap_or_default())
+    }
+
+    #[tokio::test]
+    async fn test_route_grammar() {
+        let app = Route::new().nest(
+            "/v2",
+            Route::new()
+                .at(Manifests::path(), get(echo))
+                .at(Blobs::path(), get(echo)),
+        );
+        let cli = poem::test::TestClient::new(app);
+
+        let digest = "sha256:b94d27b9934d3e8a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9d";
+        let long_tag = "a".repeat(128);
+        let too_long_tag = "a".repeat(129);
+
+        let valid_names = [
+            "a",
+            "0/1",
+            "a/b/c",
+            "library/redis",
+            "my.team/app",
+            "foo_bar",
+            "foo__bar",
+            "foo---bar",
+            "my-org/my.app_v2",
+        ];
+        for name in valid_names {
+            let resp = cli.get(format!("/v2/{name}/manifests/latest")).send().await;
+            resp.assert_status_is_ok();
+            resp.assert_text(format!("{name} latest")).await;
+
+            let resp = cli.get(format!("/v2/{name}/blobs/{digest}")).send().await;
+            resp.assert_status_is_ok();
+            resp.assert_text(format!("{name} {digest}")).await;
+        }
+
+        let invalid_names = [
+            "My/App",
+            ".foo",
+            "-foo",
+            "foo-",
+            "foo./bar",
+            "foo___bar",
+            "foo//bar",
+            "foo/_bar",
+        ];
+        for name in invalid_names {
+            let resp = cli.get(format!("/v2/{name}/manifests/latest")).send().await;
+            resp.assert_status(StatusCode::NOT_FOUND);
+        }
+
+        let valid_references = ["latest", "v1.0-rc_1", "_x", "LATEST", digest, long_tag.as_str()];
+        for reference in valid_references {
+            let resp = cli.get(format!("/v2/library/redis/manifests/{reference}")).send().await;
+            resp.assert_status_is_ok();
+            resp.assert_text(format!("library/redis {reference}")).await;
+        }
+
+        // References that look like a digest are matched here, and validated by the proxy api
+        let invalid_references = [".x", "-x", "latest!", too_long_tag.as_str()];
+        for reference in invalid_references {
+            let resp = cli.get(format!("/v2/library/redis/manifests/{reference}")).send().await;
+            resp.assert_status(StatusCode::NOT_FOUND);
+        }
+
+        // Blobs are only addressed by digest
+        let resp = cli.get("/v2/library/redis/blobs/latest").send().await;
+        resp.assert_status(StatusCode::NOT_FOUND);
+    }
 }
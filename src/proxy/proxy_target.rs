@@ -1,16 +1,21 @@
-use std::{fmt::Display, path::PathBuf, str::FromStr};
+use std::{path::PathBuf, str::FromStr};
 
 use hyper::{Method, Response};
 use lifec::prelude::{AttributeIndex, ThunkContext};
-use logos::Logos;
 use poem::{Body, Request, RequestBuilder};
 use tracing::{event, Level};
 
+use crate::content::read_verified;
 use crate::content::Descriptor;
+use crate::content::MediaTypeRegistry;
+use crate::Error;
 
 mod object;
 pub use object::Object;
 
+mod image_reference;
+pub use image_reference::ImageReference;
+
 /// Wrapper struct representing properties of the upstream server,
 ///
 #[derive(Debug)]
@@ -36,9 +41,24 @@ impl ProxyTarget {
         &self.object
     }
 
+    /// Returns the upstream namespace, Ex. example.azurecr.io
+    ///
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    /// Returns the repository name,
+    ///
+    pub fn repo(&self) -> &str {
+        &self.repo
+    }
+
     /// Request content w/ a descriptor from the proxy target,
     ///
-    pub async fn request_content(&self, descriptor: &Descriptor) -> Option<Vec<u8>> {
+    /// The content is verified against the digest and size of the descriptor, including content downloaded from a
+    /// redirect location, Ex. blob storage. Returns a digest or size mismatch error if the content does not match.
+    ///
+    pub async fn request_content(&self, descriptor: &Descriptor) -> Result<Vec<u8>, Error> {
         let client = self
             .context
             .client()
@@ -52,9 +72,8 @@ impl ProxyTarget {
             media_type, digest, ..
         } = descriptor;
 
-        let resource = Resource::lexer(media_type)
-            .next()
-            .expect("should return something");
+        // Media types that are not registered are requested as blobs
+        let resource = MediaTypeRegistry::kind(media_type).resource();
 
         let resource_url = format!("https://{namespace}/v2/{repo}/{resource}/{digest}");
 
@@ -64,19 +83,27 @@ impl ProxyTarget {
             .header("accept", media_type)
             .finish();
 
-        if let Some(mut response) = self.send_request(req).await {
-            if let Some(location) = response.headers().get("Location") {
-                event!(Level::DEBUG, "Following redirect from location header");
-                response = client
-                    .get(location.to_str().unwrap_or_default().parse().unwrap())
-                    .await
-                    .unwrap()
-            };
+        let mut response = self
+            .send_request(req)
+            .await
+            .ok_or_else(Error::external_dependency)?;
 
-            Self::parse_body(response).await
-        } else {
-            None
+        if response.status().is_redirection() {
+            let location = response
+                .headers()
+                .get("Location")
+                .and_then(|l| l.to_str().ok())
+                .ok_or_else(|| Error::invalid_operation("redirect is missing a location"))?;
+
+            event!(Level::DEBUG, "Following redirect from location header");
+            response = client.get(location.parse()?).await?;
         }
+
+        if !response.status().is_success() {
+            return Err(Error::external_dependency_with(response.status()));
+        }
+
+        read_verified(response.into_body(), digest, Some(descriptor.size)).await
     }
 
     /// Resolves a descriptor from a uri,
@@ -130,7 +157,7 @@ impl ProxyTarget {
                 let desc = Descriptor {
                     media_type: content_type.to_string(),
                     artifact_type: None,
-                    digest: digest.to_string(),
+                    digest: digest.parse().ok()?,
                     size: content_lengtth,
                     annotations: None,
                     urls: None,
@@ -184,7 +211,7 @@ impl ProxyTarget {
             namespace, repo, ..
         } = self;
 
-        format!("https://{namespace}/v2/{repo}/blobs/upload")
+        format!("https://{namespace}/v2/{repo}/blobs/uploads/")
     }
 
     /// Returns a blob url to the upstream target,
@@ -210,7 +237,7 @@ impl ProxyTarget {
             ..
         } = self;
 
-        format!("https://{namespace}/v2/{repo}/_oras/artifacts/referrers?digest={object}")
+        format!("https://{namespace}/v2/{repo}/referrers/{object}")
     }
 
     /// Returns a manifest url to the upstream target,
@@ -247,88 +274,18 @@ impl ProxyTarget {
 
     /// Returns an image reference for this target,
     ///
-    pub fn image_reference(&self) -> String {
-        let Self {
-            namespace,
-            repo,
-            object,
-            ..
-        } = self;
-
-        format!("{namespace}/{repo}{:#}", object)
+    pub fn image_reference(&self) -> Result<ImageReference, Error> {
+        ImageReference::try_from(self)
     }
 
     /// Returns an image reference for this target w/ a different object,
     ///
-    pub fn image_reference_with(&self, object: impl Into<Object>) -> String {
-        let ob = object.into();
-        let Self {
-            namespace, repo, ..
-        } = self;
-
-        format!("{namespace}/{repo}{:#}", ob)
-    }
-
-    /// Reads the body from a response and returns the bytes,
-    ///
-    async fn parse_body(response: Response<hyper::Body>) -> Option<Vec<u8>> {
-        match hyper::body::to_bytes(response.into_body()).await {
-            Ok(data) => {
-                event!(Level::DEBUG, "Resolved blob, len: {}", data.len());
-                Some(data.to_vec())
-            }
-            Err(err) => {
-                event!(Level::ERROR, "{err}");
-                None
-            }
-        }
+    pub fn image_reference_with(&self, object: impl Into<Object>) -> Result<ImageReference, Error> {
+        Ok(self.image_reference()?.with_object(object))
     }
-}
-
-/// Enumeration of well known resource types,
-/// 
-#[derive(Logos)]
-enum Resource {
-    /// Known supported manifest types,
-    ///
-    #[token("application/vnd.oci.artifact.manifest.v1+json")]
-    #[token("application/vnd.cncf.oras.artifact.manifest.v1+json")]
-    #[token("application/vnd.oci.image.manifest.v1+json")]
-    #[token("application/vnd.docker.distribution.manifest.v1+json")]
-    #[token("application/vnd.docker.distribution.manifest.v2+json")]
-    #[token("application/vnd.docker.distribution.manifest.list.v2+json")]
-    Manifest,
-    /// Non-exhaustive list of blobs, will default to this
-    ///
-    #[token("application/vnd.oci.image.layer.v1.tar")]
-    #[token("application/vnd.docker.container.image.v1+json")]
-    #[token("application/vnd.docker.image.rootfs.diff.tar.gzip")]
-    // #[token("application/vnd.docker.image.rootfs.foreign.diff.tar.gzip")]
-    #[token("application/vnd.docker.plugin.v1+json")]
-    #[token("application/vnd.oci.image.config.v1+json")]
-    #[token("application/vnd.oci.image.layer.v1.tar+gzip")]
-    #[token("application/vnd.oci.image.layer.v1.tar+zstd")]
-    #[token("application/gzip")]
-    #[token("application/octet-stream")]
-    #[token("application/json")]
-    #[token("text/plain")]
-    Blobs,
-    #[error]
-    #[regex(r"[ \t\n\f]+", logos::skip)]
-    Error,
-}
 
-impl Display for Resource {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Resource::Manifest => write!(f, "manifests"),
-            Resource::Blobs => write!(f, "blobs"),
-            Resource::Error => write!(f, "blobs"),
-        }
-    }
 }
 
-
 impl From<&Request> for ProxyTarget {
     fn from(req: &Request) -> Self {
         let ns = req.uri().host().expect("should have a host");
@@ -356,12 +313,7 @@ impl From<&Request> for ProxyTarget {
             namespace: ns.to_string(),
             repo,
             context: ThunkContext::default(),
-            object: {
-                match Object::lexer(&reference).next() {
-                    Some(obj) => obj,
-                    None => panic!("A reference is required"),
-                }
-            },
+            object: Object::parse(&reference),
         }
     }
 }
@@ -379,9 +331,9 @@ impl TryFrom<&ThunkContext> for ProxyTarget {
                 repo,
                 object: {
                     if let Some(digest) = tc.search().find_symbol("digest") {
-                        Object::Digest(digest)
+                        Object::Digest(digest.parse()?)
                     } else if let Some(reference) = tc.search().find_symbol("REFERENCE") {
-                        Object::lexer(&reference).next().unwrap_or(Object::Error)
+                        Object::parse(&reference)
                     } else {
                         Object::Error
                     }
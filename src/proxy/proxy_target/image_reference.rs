@@ -0,0 +1,323 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use lifec::prelude::ThunkContext;
+
+use crate::{Digest, Error};
+
+use super::{Object, ProxyTarget};
+
+/// Registry that references w/o a registry are resolved against,
+///
+const DOCKER_HUB: &str = "docker.io";
+
+/// Aliases of the Docker Hub registry,
+///
+const DOCKER_HUB_ALIASES: &[&str] = &["docker.io", "index.docker.io", "registry-1.docker.io"];
+
+/// Parsed image reference in the format `registry[:port]/repo[:tag][@digest]`,
+///
+/// References are normalized the same way the docker cli normalizes them, so short Docker Hub names are expanded,
+/// Ex. `redis` -> `docker.io/library/redis`, `user/app:v1` -> `docker.io/user/app:v1`.
+///
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ImageReference {
+    /// Registry host, including the port if one was set,
+    ///
+    registry: String,
+    /// Repository name,
+    ///
+    repository: String,
+    /// Tag, if set,
+    ///
+    tag: Option<String>,
+    /// Digest, if set,
+    ///
+    digest: Option<Digest>,
+}
+
+impl ImageReference {
+    /// Returns a normalized image reference,
+    ///
+    pub fn new(
+        registry: impl AsRef<str>,
+        repository: impl AsRef<str>,
+        tag: Option<String>,
+        digest: Option<Digest>,
+    ) -> Result<Self, Error> {
+        let mut registry = registry.as_ref().to_string();
+        let mut repository = repository.as_ref().to_string();
+
+        if DOCKER_HUB_ALIASES.contains(&registry.as_str()) {
+            registry = DOCKER_HUB.to_string();
+
+            // Official images are in the library namespace
+            if !repository.contains('/') {
+                repository = format!("library/{repository}");
+            }
+        }
+
+        if !is_valid_registry(&registry) {
+            return Err(Error::invalid_reference("invalid registry"));
+        }
+
+        if !repository.split('/').all(is_valid_component) {
+            return Err(Error::invalid_reference("invalid repository name"));
+        }
+
+        // From the distribution spec, clients should limit the registry and repository to 255 characters
+        if registry.len() + repository.len() + 1 > 255 {
+            return Err(Error::invalid_reference("repository name is too long"));
+        }
+
+        if let Some(tag) = tag.as_ref() {
+            if !matches!(Object::parse(tag), Object::Reference(_)) {
+                return Err(Error::invalid_reference("invalid tag"));
+            }
+        }
+
+        Ok(Self {
+            registry,
+            repository,
+            tag,
+            digest,
+        })
+    }
+
+    /// Parses an image reference, Ex. redis, docker.io/library/redis:7, localhost:5000/app@sha256:<hex>
+    ///
+    pub fn parse(reference: impl AsRef<str>) -> Result<Self, Error> {
+        Self::parse_with_registry(reference, DOCKER_HUB)
+    }
+
+    /// Parses an image reference, references w/o a registry are resolved against the given registry instead of Docker Hub,
+    ///
+    pub fn parse_with_registry(reference: impl AsRef<str>, default_registry: impl AsRef<str>) -> Result<Self, Error> {
+        let reference = reference.as_ref().trim();
+
+        let (name, digest) = match reference.split_once('@') {
+            Some((name, digest)) => (name, Some(Digest::parse(digest)?)),
+            None => (reference, None),
+        };
+
+        // A ':' after the last '/' is a tag, otherwise it is the port of the registry
+        let (name, tag) = match name.rfind(':') {
+            Some(pos) if !name[pos..].contains('/') => (&name[..pos], Some(name[pos + 1..].to_string())),
+            _ => (name, None),
+        };
+
+        match name.split_once('/') {
+            Some((registry, repository)) if is_registry(registry) => Self::new(registry, repository, tag, digest),
+            _ => Self::new(default_registry.as_ref(), name, tag, digest),
+        }
+    }
+
+    /// Returns the registry host,
+    ///
+    pub fn registry(&self) -> &str {
+        &self.registry
+    }
+
+    /// Returns the repository name,
+    ///
+    pub fn repository(&self) -> &str {
+        &self.repository
+    }
+
+    /// Returns the tag, if set,
+    ///
+    pub fn tag(&self) -> Option<&str> {
+        self.tag.as_deref()
+    }
+
+    /// Returns the digest, if set,
+    ///
+    pub fn digest(&self) -> Option<&Digest> {
+        self.digest.as_ref()
+    }
+
+    /// Returns the object this reference points to,
+    ///
+    /// The digest is preferred over the tag, and references w/o either point to the `latest` tag.
+    ///
+    pub fn object(&self) -> Object {
+        match (self.digest.as_ref(), self.tag.as_ref()) {
+            (Some(digest), _) => Object::Digest(digest.clone()),
+            (None, Some(tag)) => Object::Reference(tag.to_string()),
+            (None, None) => Object::Reference("latest".to_string()),
+        }
+    }
+
+    /// Returns a copy of this reference w/ a different object,
+    ///
+    pub fn with_object(&self, object: impl Into<Object>) -> Self {
+        let (tag, digest) = match object.into() {
+            Object::Reference(tag) => (Some(tag), None),
+            Object::Digest(digest) => (None, Some(digest)),
+            Object::Error => (None, None),
+        };
+
+        Self {
+            registry: self.registry.clone(),
+            repository: self.repository.clone(),
+            tag,
+            digest,
+        }
+    }
+}
+
+impl FromStr for ImageReference {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl Display for ImageReference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.registry, self.repository)?;
+
+        if let Some(tag) = self.tag.as_ref() {
+            write!(f, ":{tag}")?;
+        }
+
+        if let Some(digest) = self.digest.as_ref() {
+            write!(f, "@{digest}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl TryFrom<&ProxyTarget> for ImageReference {
+    type Error = Error;
+
+    fn try_from(target: &ProxyTarget) -> Result<Self, Self::Error> {
+        Ok(Self::new(&target.namespace, &target.repo, None, None)?.with_object(target.object.clone()))
+    }
+}
+
+impl From<(&ImageReference, &ThunkContext)> for ProxyTarget {
+    fn from((reference, context): (&ImageReference, &ThunkContext)) -> Self {
+        Self {
+            namespace: reference.registry.to_string(),
+            repo: reference.repository.to_string(),
+            context: context.clone(),
+            object: reference.object(),
+        }
+    }
+}
+
+/// Returns true if the first component of a reference is a registry host, the same way the docker cli decides,
+///
+fn is_registry(component: &str) -> bool {
+    component.contains('.')
+        || component.contains(':')
+        || component == "localhost"
+        || component.chars().any(|c| c.is_ascii_uppercase())
+}
+
+/// Returns true if the registry is a valid host w/ an optional port,
+///
+fn is_valid_registry(registry: &str) -> bool {
+    let (host, port) = match registry.rsplit_once(':') {
+        Some((host, port)) => (host, Some(port)),
+        None => (registry, None),
+    };
+
+    let valid_host = !host.is_empty()
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+
+    let valid_port = port.map_or(true, |p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()));
+
+    valid_host && valid_port
+}
+
+/// Returns true if a path component of a repository name is valid, from the distribution spec,
+///
+/// `[a-z0-9]+((\.|_|__|-+)[a-z0-9]+)*`
+///
+fn is_valid_component(component: &str) -> bool {
+    let is_alphanumeric = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit();
+
+    component.starts_with(is_alphanumeric)
+        && component.ends_with(is_alphanumeric)
+        && component
+            .split(is_alphanumeric)
+            .all(|separator| matches!(separator, "" | "." | "_" | "__") || separator.chars().all(|c| c == '-'))
+}
+
+#[allow(unused_imports)]
+mod tests {
+    use lifec::prelude::ThunkContext;
+
+    use super::ImageReference;
+    use crate::{Object, ProxyTarget};
+
+    #[test]
+    fn test_image_reference() {
+        let digest = "sha256:b94d27b9934d3e8a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9d";
+
+        // Input, normalized reference
+        let valid = [
+            ("redis", "docker.io/library/redis"),
+            ("redis:7", "docker.io/library/redis:7"),
+            ("user/app:v1", "docker.io/user/app:v1"),
+            ("index.docker.io/redis", "docker.io/library/redis"),
+            ("registry-1.docker.io/library/redis:7", "docker.io/library/redis:7"),
+            ("localhost/app", "localhost/app"),
+            ("localhost:5000/app:latest", "localhost:5000/app:latest"),
+            ("myregistry.azurecr.io/team/app", "myregistry.azurecr.io/team/app"),
+            ("myregistry.azurecr.io:443/my.team/app_v2:1.0-rc_1", "myregistry.azurecr.io:443/my.team/app_v2:1.0-rc_1"),
+        ];
+        for (input, expected) in valid {
+            let reference = ImageReference::parse(input).unwrap();
+            assert_eq!(expected, reference.to_string());
+            assert_eq!(reference, reference.to_string().parse::<ImageReference>().unwrap());
+        }
+
+        let reference = ImageReference::parse(format!("localhost:5000/app:v1@{digest}")).unwrap();
+        assert_eq!("localhost:5000", reference.registry());
+        assert_eq!("app", reference.repository());
+        assert_eq!(Some("v1"), reference.tag());
+        assert_eq!(Some(digest), reference.digest().map(|d| d.as_str()));
+        assert_eq!(Object::Digest(digest.parse().unwrap()), reference.object());
+        assert_eq!(format!("localhost:5000/app:v1@{digest}"), reference.to_string());
+        assert_eq!(Object::Reference("latest".to_string()), ImageReference::parse("redis").unwrap().object());
+
+        // References w/o a registry can be resolved against a different registry
+        let reference = ImageReference::parse_with_registry("library/redis:7", "example.azurecr.io").unwrap();
+        assert_eq!("example.azurecr.io/library/redis:7", reference.to_string());
+        let reference = ImageReference::parse_with_registry("localhost:5000/redis", "example.azurecr.io").unwrap();
+        assert_eq!("localhost:5000/redis", reference.to_string());
+
+        let invalid = [
+            "",
+            "Redis",
+            "redis:",
+            "redis:-tag",
+            "redis@sha256:abc",
+            "docker.io/library//redis",
+            "docker.io/library/redis_",
+            "localhost:port/app",
+            "-registry.io/app",
+            "https://myregistry.azurecr.io/v2/app/manifests/latest",
+        ];
+        for input in invalid {
+            assert!(ImageReference::parse(input).is_err(), "{input}");
+        }
+
+        // Converts to and from a proxy target
+        let reference = ImageReference::parse(format!("myregistry.azurecr.io/team/app@{digest}")).unwrap();
+        let target = ProxyTarget::from((&reference, &ThunkContext::default()));
+        assert_eq!(format!("https://myregistry.azurecr.io/v2/team/app/blobs/{digest}"), target.blob_url());
+        assert_eq!(reference, ImageReference::try_from(&target).unwrap());
+        assert_eq!(reference, target.image_reference().unwrap());
+    }
+}
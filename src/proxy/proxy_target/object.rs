@@ -2,9 +2,11 @@ use std::fmt::Display;
 
 use logos::{Lexer, Logos};
 
+use crate::Digest;
+
 /// Enumeration of object types for repos, can either be a reference tag or sha digest
 /// 
-#[derive(Logos, Debug, PartialEq, Eq)]
+#[derive(Logos, Debug, Clone, PartialEq, Eq)]
 pub enum Object {
     /// From OCI documentation,
     ///
@@ -13,18 +15,35 @@ pub enum Object {
     ///[a-zA-Z0-9_][a-zA-Z0-9._-]{0,127}
     /// ```
     ///
-    #[regex("[a-zA-Z0-9_][a-zA-Z0-9._-]+", on_reference)]
+    #[regex("[a-zA-Z0-9_][a-zA-Z0-9._-]*", on_reference)]
     Reference(String),
-    /// Parses a sha-digest, currently 256 and 512 are supported
+    /// Parses a sha-digest, currently 256 and 512 are supported, digests w/ an invalid length are an error
     ///
     #[regex("sha512:[a-f0-9]+", on_digest)]
     #[regex("sha256:[a-f0-9]+", on_digest)]
-    Digest(String),
+    Digest(Digest),
     #[error]
     #[regex(r"[ \t\n\f]+", logos::skip)]
     Error,
 }
 
+impl Object {
+    /// Parses an object, returns Object::Error unless the entire input is a single tag or digest,
+    ///
+    /// The lexer alone stops at the first character that cannot continue a token, so `latest:1` would lex to the tag
+    /// `latest`.
+    ///
+    pub fn parse(object: impl AsRef<str>) -> Self {
+        let object = object.as_ref();
+        let mut lexer = Object::lexer(object);
+
+        match (lexer.next(), lexer.span(), lexer.next()) {
+            (Some(parsed), span, None) if span == (0..object.len()) => parsed,
+            _ => Object::Error,
+        }
+    }
+}
+
 impl Display for Object {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -54,18 +73,8 @@ fn on_reference(lexer: &mut Lexer<Object>) -> Option<String> {
     }
 }
 
-fn on_digest(lexer: &mut Lexer<Object>) -> Option<String> {
-    let digest = &lexer.remainder()[..];
-
-    if lexer.slice().starts_with("sha256") {
-        assert!(digest.len() < 64);
-    } else if lexer.slice().starts_with("sha512") {
-        assert!(digest.len() < 128);
-    } else {
-        panic!("unspported digest format")
-    }
-
-    Some(format!("{}{}", lexer.slice(), digest))
+fn on_digest(lexer: &mut Lexer<Object>) -> Option<Digest> {
+    Digest::parse(lexer.slice()).ok()
 }
 
 
@@ -73,25 +82,36 @@ fn on_digest(lexer: &mut Lexer<Object>) -> Option<String> {
 fn test_object_parser() {
     // Test digests
     let mut lexer =
-        Object::lexer("sha256:b94d27b9934d3e8a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9");
+        Object::lexer("sha256:b94d27b9934d3e8a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9d");
 
     assert_eq!(
         lexer.next(),
         Some(Object::Digest(
-            "sha256:b94d27b9934d3e8a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9".to_string()
+            "sha256:b94d27b9934d3e8a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9d".parse().unwrap()
         ))
     );
 
     let mut lexer =
-        Object::lexer("sha256:c93e919e9985d48c6142530fa902745b76b28873488a64f9422302c620d170");
+        Object::lexer("sha512:9b71d224bd62f3785d96d46ad3ea3d73319bfbc2890caadae2dff72519673ca72323c3d99ba5c11d7c7acc6e14b8c5da0c4663475c2e5c3adef46f73bcdec043");
 
     assert_eq!(
         lexer.next(),
         Some(Object::Digest(
-            "sha256:c93e919e9985d48c6142530fa902745b76b28873488a64f9422302c620d170".to_string()
+            "sha512:9b71d224bd62f3785d96d46ad3ea3d73319bfbc2890caadae2dff72519673ca72323c3d99ba5c11d7c7acc6e14b8c5da0c4663475c2e5c3adef46f73bcdec043".parse().unwrap()
         ))
     );
 
+    // Test digests w/ an invalid length are an error instead of a panic
+    let mut lexer =
+        Object::lexer("sha256:c93e919e9985d48c6142530fa902745b76b28873488a64f9422302c620d170");
+
+    assert_eq!(lexer.next(), Some(Object::Error));
+
+    let mut lexer =
+        Object::lexer("sha512:b94d27b9934d3e8a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9d");
+
+    assert_eq!(lexer.next(), Some(Object::Error));
+
     // Test tags
     let mut lexer = Object::lexer("demo_.thats-really_cool");
 
@@ -116,3 +136,20 @@ fn test_object_parser() {
         Some(Object::Reference("_9demo_.thats-reall8y_cool".to_string()))
     );
 }
+
+#[test]
+fn test_object_grammar() {
+    let digest = "sha256:b94d27b9934d3e8a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9d";
+    let long_tag = "a".repeat(128);
+
+    for reference in ["latest", "v1.0-rc_1", "_x", "LATEST", "sha256", long_tag.as_str()] {
+        assert_eq!(Object::Reference(reference.to_string()), Object::parse(reference));
+    }
+    assert_eq!(Object::Digest(digest.parse().unwrap()), Object::parse(digest));
+
+    // The entire input must be a single tag or digest
+    let too_long_tag = "a".repeat(129);
+    for invalid in [".x", "-x", "latest:1", "latest!", " latest", "", "md5:5d41402abc4b2a76b9719d911017c592", too_long_tag.as_str()] {
+        assert_eq!(Object::Error, Object::parse(invalid), "{invalid}");
+    }
+}
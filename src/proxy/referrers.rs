@@ -0,0 +1,27 @@
+use super::proxy_route::RouteParameters;
+
+/// Route plugin to handle registry referrers requests,
+///
+/// The `artifactType` query parameter is forwarded to the upstream. Use the `.referrers_fallback` plugin in the operation
+/// to support upstreams that do not implement the referrers api.
+///
+/// Example:
+/// : .mirror     <azurecr.io>
+/// : .host       <address> resolve, pull
+///
+/// + .proxy      <address>
+/// : .referrers
+/// : .get        <operation-name>
+///
+#[derive(Default, Clone)]
+pub struct Referrers;
+
+impl RouteParameters for Referrers {
+    fn path() -> &'static str {
+        concat!(repo_path!("referrers"), "/:reference<^", digest_regex!(), "$>")
+    }
+
+    fn ident() -> &'static str {
+        "referrers"
+    }
+}
@@ -0,0 +1,27 @@
+use super::proxy_route::RouteParameters;
+
+/// Route plugin to handle registry tag list requests,
+///
+/// The `n` and `last` query parameters are forwarded to the upstream, and the `Link` header of the upstream response is
+/// rewritten so that clients continue paging through the mirror.
+///
+/// Example:
+/// : .mirror     <azurecr.io>
+/// : .host       <address> resolve, pull
+///
+/// + .proxy      <address>
+/// : .tags
+/// : .get        <operation-name>
+///
+#[derive(Default, Clone)]
+pub struct Tags;
+
+impl RouteParameters for Tags {
+    fn path() -> &'static str {
+        concat!(repo_path!("tags"), "/:reference<^list$>")
+    }
+
+    fn ident() -> &'static str {
+        "tags"
+    }
+}